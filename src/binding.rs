@@ -2,6 +2,10 @@ use crate::rellcore::*;
 use crate::rellcore::errors::*;
 use crate::parser::*;
 use crate::tree::*;
+use crate::symbols::SymbolsTable;
+use crate::query::Aggregate;
+use crate::trace::{ self, TraceEvent };
+use std::cmp::Ordering;
 use std::collections::{BTreeMap, HashSet};
 
 #[derive(Debug)]
@@ -12,16 +16,181 @@ struct BindingVarState
     bound_vars: Vec<(SID, SID)>,
 }
 
+// A set of compatible variable values, along with the node each statement matched
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct BindingMatch
+{
+    pub vars: BTreeMap<SID, SID>,    // Variable SID -> Value SID
+    pub nids: BTreeMap<String, NID>, // Pre-Bound Statement -> Matched Node
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Comparison
+{
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+impl Comparison
+{
+    // Longest first, so `<=` isn't taken for `<`
+    const OPERATORS: [(&'static str, Comparison); 6] = [("<=", Comparison::Le), (">=", Comparison::Ge),
+                                                        ("==", Comparison::Eq), ("!=", Comparison::Ne),
+                                                        ("<", Comparison::Lt),  (">", Comparison::Gt)];
+
+    // Numbers compare numerically and literals lexicographically, values that can't be
+    // compared (e.g. a number and a literal) are only ever different
+    pub fn holds(&self, lhs: &RellSymValue, rhs: &RellSymValue) -> bool
+    {
+        let ordering = lhs.partial_cmp(rhs);
+        match self
+        {
+            Comparison::Lt => ordering == Some(Ordering::Less),
+            Comparison::Le => matches!(ordering, Some(Ordering::Less) | Some(Ordering::Equal)),
+            Comparison::Gt => ordering == Some(Ordering::Greater),
+            Comparison::Ge => matches!(ordering, Some(Ordering::Greater) | Some(Ordering::Equal)),
+            Comparison::Eq => ordering == Some(Ordering::Equal),
+            Comparison::Ne => ordering != Some(Ordering::Equal),
+        }
+    }
+}
+
+// A comparison between two variables, constants or aggregates, e.g. `H < 10`, `X != Y`
+// or `count(X : X.in!boat) > 2`. `not H < 10` holds whenever `H < 10` doesn't
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constraint
+{
+    pub lhs: String,
+    pub op: Comparison,
+    pub rhs: String,
+    pub negated: bool,
+}
+impl Constraint
+{
+    // None if the prior is not a comparison (i.e. it is a statement)
+    pub fn parse<S>(prior: S) -> Option<Self>
+        where S: AsRef<str>
+    {
+        let prior = prior.as_ref().trim();
+        let (negated, prior) = match prior.strip_prefix(BindingState::NEGATION_KEYWORD)
+        {
+            Some(prior) => (true, prior),
+            None        => (false, prior),
+        };
+        Comparison::OPERATORS.iter().find_map(|(symbol, op)| {
+            match RellParser::split_top_level(prior, symbol).as_slice()
+            {
+                [lhs, rhs] => Some(Self { lhs: lhs.trim().to_string(), op: *op, rhs: rhs.trim().to_string(), negated }),
+                _ => None
+            }
+        })
+    }
+
+    fn resolve_operand(&self, operand: &str, tree: &RellTree) -> Result<Operand>
+    {
+        if let Some(aggregate) = Aggregate::parse(operand)
+        {
+            return Ok(match aggregate.evaluate(tree)?
+            {
+                Some(val) => Operand::Constant(val),
+                None      => Operand::Undefined,
+            });
+        }
+
+        // A lone operand isn't a path, so `H > 2.5` doesn't need the parentheses of a statement
+        if let Some(Ok(num)) = RellParser::parse_numeric(operand)
+        {
+            return Ok(Operand::Constant(num));
+        }
+
+        // Most likely a typo (`nto H < 10`), it would only ever compare as a literal
+        if operand.contains(char::is_whitespace) && RellParser::unquote(operand).is_none()
+        {
+            return Err(Error::MalformedRule { rule: self.to_string(),
+                                              reason: format!("`{}` is not a single variable or constant", operand) });
+        }
+
+        let symbols = &tree.symbols;
+        let (_, syms) = RellParser::parse_simple_statement(operand, symbols)?;
+        match syms.as_slice()
+        {
+            [sym] => Ok(match sym.get_val()
+            {
+                RellSymValue::Identifier(name) => Operand::Variable(symbols.get_sid_no_binding(name)),
+                val => Operand::Constant(val.clone()),
+            }),
+            _ => Err(Error::MalformedRule { rule: self.to_string(),
+                                            reason: format!("`{}` is not a single variable or constant", operand) }),
+        }
+    }
+}
+
+impl std::fmt::Display for Constraint
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        let op = Comparison::OPERATORS.iter().find(|(_, op)| *op == self.op).unwrap().0;
+        let not = if self.negated { BindingState::NEGATION_KEYWORD } else { "" };
+        write!(f, "{}{} {} {}", not, self.lhs, op, self.rhs)
+    }
+}
+
+enum Operand
+{
+    Variable(SID),
+    Constant(RellSymValue),
+    Undefined, // e.g. the max of nothing
+}
+impl Operand
+{
+    // None for variables the match doesn't bind
+    fn value<'a>(&'a self, m: &BindingMatch, symbols: &'a SymbolsTable) -> Option<&'a RellSymValue>
+    {
+        match self
+        {
+            Operand::Variable(var) => Some(symbols.get_sym(m.vars.get(var)?)?.get_val()),
+            Operand::Constant(val) => Some(val),
+            Operand::Undefined     => None,
+        }
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct BindingState
 {
     binding_statements: BTreeMap<String, Option<Vec<BindingVarState>>>, // Pre-Bound Statement -> BindingState
+    negated_statements: BTreeMap<String, Option<Vec<BindingVarState>>>, // Statements that must NOT hold
+    constraints: Vec<Constraint>,                                       // Comparisons every binding must satisfy
+    allow_shared_values: bool,                                          // Distinct variables may bind the same symbol
     is_bound: bool
 }
 impl BindingState
 {
+    // Priors starting with this keyword must NOT hold
+    pub const NEGATION_KEYWORD: &'static str = "not ";
+
     pub fn new() -> Self { Self::default() }
 
+    // Adds a prior as written in rules and queries, e.g. `X.in.Y`, `not X.in.Y` or `H < 10`
+    pub fn add_prior<S>(&mut self, prior: S) -> &mut Self
+        where S: AsRef<str>
+    {
+        let prior = prior.as_ref().trim();
+        if let Some(constraint) = Constraint::parse(prior)
+        {
+            return self.add_constraint(constraint);
+        }
+
+        match prior.strip_prefix(Self::NEGATION_KEYWORD)
+        {
+            Some(negated) => self.add_negated_statement(negated.trim()),
+            None          => self.add_statement(prior),
+        }
+    }
+
     pub fn add_statement<S>(&mut self, statement: S) -> &mut Self
         where S: AsRef<str>
     {
@@ -30,15 +199,46 @@ impl BindingState
         self
     }
 
-    pub fn generate_compatible_on(&mut self, tree: &RellTree) -> Vec<BTreeMap<SID, SID>>
+    // Negation as failure: any binding for which the statement can be found in the tree
+    // gets discarded. Variables only present in negated statements match anything
+    pub fn add_negated_statement<S>(&mut self, statement: S) -> &mut Self
+        where S: AsRef<str>
+    {
+        let statement = statement.as_ref().to_string();
+        self.negated_statements.entry(statement).or_insert(None);
+        self
+    }
+
+    // By default no two variables can take the same (non numeric) value, so `X.likes.Y`
+    // won't match `bob.likes.bob`. Allowing it leaves that to explicit `X != Y` constraints
+    pub fn allow_shared_values(&mut self, allow: bool) -> &mut Self
+    {
+        self.allow_shared_values = allow;
+        self
+    }
+
+    // Variables in constraints have to be bound by some (non negated) statement
+    pub fn add_constraint(&mut self, constraint: Constraint) -> &mut Self
     {
-        self.bind_all(tree);
-        self.generate_compatible()
+        self.constraints.push(constraint);
+        self
+    }
+
+    pub fn generate_compatible_on(&mut self, tree: &RellTree) -> Result<Vec<BTreeMap<SID, SID>>>
+    {
+        self.bind_all(tree)?;
+        self.generate_compatible(tree)
+    }
+
+    pub fn generate_matches_on(&mut self, tree: &RellTree) -> Result<Vec<BindingMatch>>
+    {
+        self.bind_all(tree)?;
+        self.generate_matches(tree)
     }
 
     pub fn get_all_bound_paths_for<S>(&self, statement: S) -> Vec<String> where S: AsRef<str>
     {
-        match self.binding_statements.get(&statement.as_ref().to_string())
+        match self.binding_statements.get(statement.as_ref())
         {
             Some(Some(bound_variables)) => {
                bound_variables.iter().map(|bvs| bvs.path.clone()).collect()
@@ -47,40 +247,56 @@ impl BindingState
         }
     }
 
-    fn bind_all(&mut self, tree: &RellTree)
+    fn bind_all(&mut self, tree: &RellTree) -> Result<()>
     {
         
         let mut new_bs = BTreeMap::new();
         for statement in self.binding_statements.keys()
         {
             new_bs.insert(statement.clone(), Some(
-                self.bind_statement_to_tree(statement, tree).unwrap()
+                self.bind_statement_to_tree(statement, tree)?
             ));
         }
         self.binding_statements = new_bs;
+
+        let mut new_neg = BTreeMap::new();
+        for statement in self.negated_statements.keys()
+        {
+            new_neg.insert(statement.clone(), Some(
+                self.bind_statement_to_tree(statement, tree)?
+            ));
+        }
+        self.negated_statements = new_neg;
         self.is_bound = true;
+        Ok(())
+    }
+
+    fn generate_compatible(&self, tree: &RellTree) -> Result<Vec<BTreeMap<SID, SID>>>
+    {
+        Ok(self.generate_matches(tree)?.into_iter().map(|m| m.vars).collect())
     }
 
-    fn generate_compatible(&self) -> Vec<BTreeMap<SID, SID>>
+    fn generate_matches(&self, tree: &RellTree) -> Result<Vec<BindingMatch>>
     {
         if !self.is_bound
         {
             panic!("Unbound Binding State cannot be used to generate compatible bindings");
         }
         
-        let mut valid_dictionaries = vec![BTreeMap::new()];
+        let mut valid_matches = vec![BindingMatch::default()];
 
-        for binding_states in self.binding_statements.values()
+        for (statement, binding_states) in &self.binding_statements
         {
-            let mut new_valid_dicts = vec![];
-            while !valid_dictionaries.is_empty() && binding_states.is_some()
+            let mut new_valid_matches = vec![];
+            while !valid_matches.is_empty() && binding_states.is_some()
             {
-                let cur_dic = valid_dictionaries.pop().unwrap();
+                let cur_match = valid_matches.pop().unwrap();
                 let mut compatible;
 
                 for bs in binding_states.as_ref().unwrap()
                 {
-                    let mut cur_dic = cur_dic.clone();
+                    let mut cur_match = cur_match.clone();
+                    let cur_dic = &mut cur_match.vars;
                     compatible = true;
 
                     for (b_var_name, b_var_val) in &bs.bound_vars
@@ -102,49 +318,86 @@ impl BindingState
                         }
                     }
 
-                    compatible &=  {
-                        // Make sure no 2 variables have the same value
-                        // TODO: Should number vars be able to?
+                    compatible &= self.allow_shared_values || {
+                        // Make sure no 2 variables have the same value, numbers are
+                        // quantities rather than entities so they can repeat
                         let mut no_repeats = true;
                         let mut used_vars = HashSet::new();
                         for (_, value) in cur_dic.iter()
                         {
-                            no_repeats &= used_vars.insert(value); 
+                            let numeric = tree.symbols.get_sym(value).is_some_and(|sym| sym.get_val().is_numeric());
+                            no_repeats &= numeric || used_vars.insert(value);
                         }
                         no_repeats
                     };
                     
                     if compatible
                     { 
-                        new_valid_dicts.push(cur_dic);
+                        cur_match.nids.insert(statement.clone(), bs.nid);
+                        new_valid_matches.push(cur_match);
                     }
                 }
             }
-            valid_dictionaries = new_valid_dicts;
+            valid_matches = new_valid_matches;
         }
 
-        valid_dictionaries
+        // Drop every binding that makes any of the negated statements true
+        for binding_states in self.negated_statements.values().flatten()
+        {
+            valid_matches.retain(|m| {
+                !binding_states.iter().any(|bs| {
+                    bs.bound_vars.iter().all(|(b_var_name, b_var_val)| {
+                        m.vars.get(b_var_name).is_none_or(|sid| sid == b_var_val)
+                    })
+                })
+            });
+        }
+
+        for constraint in &self.constraints
+        {
+            let lhs = constraint.resolve_operand(&constraint.lhs, tree)?;
+            let rhs = constraint.resolve_operand(&constraint.rhs, tree)?;
+            valid_matches.retain(|m| {
+                match (lhs.value(m, &tree.symbols), rhs.value(m, &tree.symbols))
+                {
+                    (Some(l), Some(r)) => constraint.op.holds(l, r) != constraint.negated,
+                    _ => false
+                }
+            });
+        }
+
+        Ok(valid_matches)
     }
 
     fn bind_statement_to_tree<S>(&self, statement: S, tree: &RellTree) -> Result<Vec<BindingVarState>>
         where S: AsRef<str>
     {
-        let (_, parsed_symbols) = RellParser::parse_simple_statement(statement, &tree.symbols)?;
-        self.bind_parsed_statement_to_tree(tree, &parsed_symbols)
+        let statement = statement.as_ref();
+        let (parsed_nodes, parsed_symbols) = RellParser::parse_simple_statement(statement, &tree.symbols)?;
+        let bound = self.bind_parsed_statement_to_tree(tree, &parsed_nodes, &parsed_symbols)?;
+
+        trace::emit(|| TraceEvent::Bound { statement: statement.to_string(), matches: bound.iter().map(|bvs| bvs.path.clone()).collect() });
+        Ok(bound)
     }
 
-    fn bind_parsed_statement_to_tree(&self, tree: &RellTree, stmnt_symbols: &[RellSym]) -> Result<Vec<BindingVarState>>
+    fn bind_parsed_statement_to_tree(&self, tree: &RellTree, stmnt_nodes: &[RellN], stmnt_symbols: &[RellSym]) -> Result<Vec<BindingVarState>>
     {
         let mut var_states_to_visit = vec![BindingVarState { nid:  RellTree::NID_ROOT,
                                                              path: "".to_string(),
                                                              bound_vars: vec![] }];
-        for sym in stmnt_symbols
+        // Like get_at_path, `!` in the pattern only matches exclusive edges while `.` matches both
+        let mut exclusive_edge = false;
+        for (stmnt_node, sym) in stmnt_nodes.iter().zip(stmnt_symbols)
         {
             let mut new_nodes_to_visit = vec![];
-            while !var_states_to_visit.is_empty()
+            while let Some(cur_n) = var_states_to_visit.pop()
             {
-                let cur_n = var_states_to_visit.pop().unwrap();
                 let node = tree.nodes.get(&cur_n.nid).unwrap();
+                if exclusive_edge && !matches!(node.edge, RellE::Exclusive(_, _))
+                {
+                    continue;
+                }
+
                 if let RellSymValue::Identifier(id) = sym.get_val()
                 {
                     let nids = match &node.edge {
@@ -153,28 +406,26 @@ impl BindingState
                         _                          =>  vec![]
                     };
 
+                    let var = if id == RellParser::WILDCARD { None } else { Some(tree.symbols.get_sid(id)) };
                     for nid in nids
                     {
-                        Self::binding_traversal_helper(&nid, tree, &cur_n.bound_vars, &cur_n.path, &mut new_nodes_to_visit, Some(&tree.symbols.get_sid(id)))
+                        Self::binding_traversal_helper(&nid, tree, &cur_n.bound_vars, &cur_n.path, &mut new_nodes_to_visit, var.as_ref())
                     }
                 }
                 else
                 {
-                    let sym_id = tree.symbols.get_sid(&sym.to_string());
+                    let sym_id = stmnt_node.sym;
                     match &node.edge
                     {
-                        RellE::Exclusive(a_sid, a_nid) =>
+                        RellE::Exclusive(a_sid, a_nid) if *a_sid == sym_id =>
                         {
-                            if *a_sid == sym_id
-                            {
-                                Self::binding_traversal_helper(&a_nid, tree, &cur_n.bound_vars, &cur_n.path, &mut new_nodes_to_visit, None);
-                            }
+                            Self::binding_traversal_helper(a_nid, tree, &cur_n.bound_vars, &cur_n.path, &mut new_nodes_to_visit, None);
                         },
                         RellE::NonExclusive(a_map) =>
                         {
                             if let Some(a_nid) = a_map.get(&sym_id)
                             {
-                                Self::binding_traversal_helper(&a_nid, tree, &cur_n.bound_vars, &cur_n.path, &mut new_nodes_to_visit, None);
+                                Self::binding_traversal_helper(a_nid, tree, &cur_n.bound_vars, &cur_n.path, &mut new_nodes_to_visit, None);
                             }
                         },
                         _ => {}
@@ -182,6 +433,7 @@ impl BindingState
                 }
             }
             var_states_to_visit = new_nodes_to_visit;
+            exclusive_edge = matches!(stmnt_node.edge, RellE::Exclusive(_, _));
         }
         Ok(var_states_to_visit)
     }
@@ -190,7 +442,7 @@ impl BindingState
                                 new_nodes_to_visit: &mut Vec<BindingVarState>, id_opt: Option<&SID>)
     {
         let mut bound_vars = bound_vars.to_owned();
-        let nnode = tree.nodes.get(&nid).unwrap();
+        let nnode = tree.nodes.get(nid).unwrap();
         let nsym = tree.symbols.get_sym(&nnode.sym).unwrap();
         let new_path = path.to_owned() + &nsym.to_string() + &nnode.edge.to_string();
         if let Some(id)  = id_opt
@@ -226,9 +478,9 @@ mod test
         let b_result_1 = bs.bind_statement_to_tree("X.in.Y", &w)?;
         let b_result_2 = bs.bind_statement_to_tree("Y.in.Z", &w)?;
 
-        let expected_result_1 = vec![vec![("X", "state"), ("Y", "country")],
-                                     vec![("X", "city"),  ("Y", "state")],
-                                     vec![("X", "other_state"), ("Y", "country")]];
+        let expected_result_1 = [vec![("X", "other_state"), ("Y", "country")],
+                                     vec![("X", "state"), ("Y", "country")],
+                                     vec![("X", "city"),  ("Y", "state")]];
         let expected_result_procd_1:Vec<Vec<(SID, SID)>> = expected_result_1.iter().map(|vars|
             vars.iter().map( |(var_n, var_v)| { (w.symbols.get_sid(var_n), w.symbols.get_sid(var_v)) } ).collect()
         ).collect();
@@ -238,9 +490,9 @@ mod test
         }).collect();
         assert_eq!(expected_result_procd_1, b_result_1_procd, "Incorrect result for binding to tree");
 
-        let expected_result_2 = vec![vec![("Y", "state"), ("Z", "country")],
-                                     vec![("Y", "city"),  ("Z", "state")],
-                                     vec![("Y", "other_state"), ("Z", "country")]];
+        let expected_result_2 = [vec![("Y", "other_state"), ("Z", "country")],
+                                     vec![("Y", "state"), ("Z", "country")],
+                                     vec![("Y", "city"),  ("Z", "state")]];
         let b_result_2_procd: Vec<Vec<(SID, SID)>> = b_result_2.iter().map(|bres| {
             bres.bound_vars.iter().map(|(bvar_n, bvar_v)| (*bvar_n, *bvar_v)).collect()
         }).collect();
@@ -264,9 +516,9 @@ mod test
         let y_sid = w.symbols.get_sid("Y");
         let z_sid = w.symbols.get_sid("Z");
 
-        bs.bind_all(&w);
+        bs.bind_all(&w)?;
 
-        let mut compatible_var_bindings = bs.generate_compatible();
+        let mut compatible_var_bindings = bs.generate_compatible(&w)?;
 
         assert_eq!(compatible_var_bindings.len(), 1, "Incorrect length for bindings result");
         assert_eq!(*compatible_var_bindings[0].get(&x_sid).unwrap(), w.symbols.get_sid("city"), "Incorrect value for binding" );
@@ -287,4 +539,184 @@ mod test
         Ok(())
     }
 
+    #[test]
+    fn test_negated_binding() -> Result<()>
+    {
+        let w = build_test_tree()?;
+        let x_sid = w.symbols.get_sid("X");
+
+        // Places in something that isn't itself in something
+        let mut bs = BindingState::new();
+        bs.add_statement("X.in.Y");
+        bs.add_negated_statement("Y.in.Z");
+
+        let bindings = bs.generate_compatible_on(&w)?;
+        assert_eq!(bindings.len(), 2, "Incorrect length for bindings result");
+        assert!(bindings.iter().all(|b| *b.get(&x_sid).unwrap() != w.symbols.get_sid("city")));
+
+        // Fully bound negation
+        let mut bs = BindingState::new();
+        bs.add_statement("X.in.country");
+        bs.add_negated_statement("X.in.state");
+        assert_eq!(bs.generate_compatible_on(&w)?.len(), 2);
+
+        let mut bs = BindingState::new();
+        bs.add_statement("X.in.Y");
+        bs.add_negated_statement("nothing.important");
+        assert!(bs.generate_compatible_on(&w)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_constraints() -> Result<()>
+    {
+        let mut w = RellTree::new();
+        w.add_statement("knight.hp!50")?;
+        w.add_statement("rabbit.hp!(2.5)")?;
+        w.add_statement("peasant.hp!10")?;
+        w.add_statement("peasant.name!dennis")?;
+        let x_sid = w.symbols.get_sid("X");
+
+        let count = |priors: &[&str], w: &RellTree| -> Result<Vec<SID>> {
+            let mut bs = BindingState::new();
+            for prior in priors
+            {
+                bs.add_prior(prior);
+            }
+            Ok(bs.generate_compatible_on(w)?.iter().map(|b| b[&x_sid]).collect())
+        };
+
+        assert_eq!(count(&["X.hp!H", "H < 10"], &w)?, vec![w.symbols.get_sid("rabbit")]);
+        assert_eq!(count(&["X.hp!H", "H <= 10"], &w)?.len(), 2);
+        assert_eq!(count(&["X.hp!H", "10 < H"], &w)?, vec![w.symbols.get_sid("knight")]);
+        assert_eq!(count(&["X.hp!H", "H >= 2.5"], &w)?.len(), 3);
+        assert_eq!(count(&["X.hp!H", "H == 50.0"], &w)?, vec![w.symbols.get_sid("knight")]);
+        assert_eq!(count(&["X.hp!H", "H != 50"], &w)?.len(), 2);
+        assert_eq!(count(&["X.hp!H", "X.name!N", "N == dennis"], &w)?, vec![w.symbols.get_sid("peasant")]);
+        assert_eq!(count(&["X.hp!H", "Y.hp!G", "H > G", "G > 5"], &w)?, vec![w.symbols.get_sid("knight")]);
+
+        // Numbers and literals are never equal, nor ordered
+        assert!(count(&["X.hp!H", "H == \"50\""], &w)?.is_empty());
+        assert!(count(&["X.hp!H", "H < zzz"], &w)?.is_empty());
+        // Unbound variables don't satisfy anything
+        assert!(count(&["X.hp!H", "Z < 100"], &w)?.is_empty());
+
+        assert!(count(&["X.hp!H", "H < a.b"], &w).is_err());
+        assert_eq!(Constraint::parse("H<=10"), Some(Constraint { lhs: "H".to_string(), op: Comparison::Le, rhs: "10".to_string(), negated: false }));
+
+        // Negated comparisons hold when the comparison doesn't, incomparable values included
+        assert_eq!(count(&["X.hp!H", "not H < 10"], &w)?.len(), 2);
+        assert_eq!(count(&["X.hp!H", "X.name!N", "not N == 10"], &w)?, vec![w.symbols.get_sid("peasant")]);
+        assert_eq!(Constraint::parse("not H < 10").unwrap().to_string(), "not H < 10");
+        assert!(count(&["X.hp!H", "nto H < 10"], &w).is_err());
+        assert_eq!(Constraint::parse("X.hp!H"), None);
+        assert_eq!(Constraint::parse("X.name!\"a < b\""), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_values() -> Result<()>
+    {
+        let mut w = RellTree::new();
+        w.add_statement("bob.likes.bob")?;
+        w.add_statement("bob.likes.alice")?;
+        w.add_statement("alice.gold!0")?;
+        w.add_statement("bob.gold!0")?;
+
+        let mut bs = BindingState::new();
+        bs.add_prior("X.likes.Y");
+        assert_eq!(bs.generate_compatible_on(&w)?.len(), 1, "Distinct by default");
+
+        bs.allow_shared_values(true);
+        assert_eq!(bs.generate_compatible_on(&w)?.len(), 2);
+
+        bs.add_prior("X != Y");
+        assert_eq!(bs.generate_compatible_on(&w)?.len(), 1);
+
+        // Numbers can always repeat
+        let mut bs = BindingState::new();
+        bs.add_prior("X.gold!G");
+        bs.add_prior("Y.gold!H");
+        assert_eq!(bs.generate_compatible_on(&w)?.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wildcards() -> Result<()>
+    {
+        let mut w = RellTree::new();
+        w.add_statement("goat.in!boat")?;
+        w.add_statement("man.in!boat")?;
+        w.add_statement("man.carries.cabbage")?;
+        let x_sid = w.symbols.get_sid("X");
+
+        let mut bs = BindingState::new();
+        bs.add_prior("_.in!boat");
+        let bindings = bs.generate_compatible_on(&w)?;
+        assert_eq!(bindings.len(), 2, "One binding per match");
+        assert!(bindings.iter().all(|b| b.is_empty()), "Wildcards aren't bound: {:?}", bindings);
+
+        // Wildcards don't join, nor count towards the all-distinct check
+        let mut bs = BindingState::new();
+        bs.add_prior("X.in!_");
+        bs.add_prior("X.carries._");
+        let bindings = bs.generate_compatible_on(&w)?;
+        assert_eq!(bindings.len(), 1);
+        assert_eq!(bindings[0][&x_sid], w.symbols.get_sid("man"));
+
+        let mut bs = BindingState::new();
+        bs.add_prior("_._._");
+        assert_eq!(bs.generate_compatible_on(&w)?.len(), 3);
+
+        let mut bs = BindingState::new();
+        bs.add_prior("X.in!boat");
+        bs.add_prior("not X.carries._");
+        let bindings = bs.generate_compatible_on(&w)?;
+        assert_eq!(bindings.len(), 1);
+        assert_eq!(bindings[0][&x_sid], w.symbols.get_sid("goat"));
+
+        Ok(())
+    }
+
+    // Same tree as tree::test::baseline_verification, through bindings instead of get_at_path
+    #[test]
+    fn binding_baseline_verification() -> Result<()>
+    {
+        let mut w = RellTree::new();
+        w.add_statement("brown.is!happy")?;
+        w.add_statement("brown.knows.stuff")?;
+        w.add_statement("brown.knows.me")?;
+        w.add_statement("brown.is!sad")?;
+        w.add_statement("brown.is!sad.today")?;
+
+        let bound_paths = |statement: &str| -> Result<Vec<String>> {
+            let mut bs = BindingState::new();
+            bs.add_statement(statement);
+            bs.generate_compatible_on(&w)?;
+            Ok(bs.get_all_bound_paths_for(statement))
+        };
+
+        assert_eq!(bound_paths("brown.is!X")?, vec!["brown.is!sad."]);
+        assert_eq!(bound_paths("brown.is.X")?, vec!["brown.is!sad."]); // !sad satifies .sad
+        assert_eq!(bound_paths("brown.is!sad.X")?, vec!["brown.is!sad.today"]);
+        assert_eq!(bound_paths("brown.knows.X")?.len(), 2);
+
+        assert!(bound_paths("brown.is!happy.X")?.is_empty()); // !happy cant be satisfied by !sad
+        assert!(bound_paths("brown!is!X")?.is_empty());       // !is can't be satisfied by .is
+        assert!(bound_paths("brown!X")?.is_empty());
+        assert!(bound_paths("brown.knows!X")?.is_empty());    // knows is non exclusive
+        assert!(bound_paths("X!is")?.is_empty());
+        assert_eq!(bound_paths("X.is!Y.today")?, vec!["brown.is!sad.today"]);
+
+        // Same for wildcards and negation
+        let mut bs = BindingState::new();
+        bs.add_prior("X.is!_");
+        bs.add_prior("not X.knows!_");
+        assert_eq!(bs.generate_compatible_on(&w)?.len(), 1);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,214 @@
+use crate::rellcore::*;
+use crate::rellcore::errors::*;
+use crate::tree::*;
+
+// What changed between two trees, as statements. Applying it to the first tree
+// (see RellTree::apply_patch) gives back the second one
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TreeDiff
+{
+    pub removed: Vec<String>,            // Paths removed along with everything under them
+    pub replaced: Vec<(String, String)>, // Exclusive values that changed, e.g. goat.in!left -> goat.in!right
+    pub added: Vec<String>,              // Leaf statements that are new
+}
+
+impl TreeDiff
+{
+    pub fn is_empty(&self) -> bool
+    {
+        self.removed.is_empty() && self.replaced.is_empty() && self.added.is_empty()
+    }
+}
+
+impl std::fmt::Display for TreeDiff
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        for path in &self.removed
+        {
+            writeln!(f, "- {}", path)?;
+        }
+        for (from, to) in &self.replaced
+        {
+            writeln!(f, "~ {} -> {}", from, to)?;
+        }
+        for path in &self.added
+        {
+            writeln!(f, "+ {}", path)?;
+        }
+        Ok(())
+    }
+}
+
+// Diff & Patch
+impl RellTree
+{
+    pub fn diff(before: &RellTree, after: &RellTree) -> TreeDiff
+    {
+        let mut diff = TreeDiff::default();
+
+        let mut node_pairs = vec![(Self::NID_ROOT, Self::NID_ROOT)];
+        while let Some((b_nid, a_nid)) = node_pairs.pop()
+        {
+            let b_node = before.nodes.get(&b_nid).unwrap();
+            let a_node = after.nodes.get(&a_nid).unwrap();
+
+            match (&b_node.edge, &a_node.edge)
+            {
+                (RellE::NonExclusive(b_emap), RellE::NonExclusive(a_emap)) =>
+                {
+                    for (sym, b_child) in b_emap
+                    {
+                        match a_emap.get(sym)
+                        {
+                            Some(a_child) => node_pairs.push((*b_child, *a_child)),
+                            None => diff.removed.push(before.get_path(b_child).unwrap()),
+                        }
+                    }
+                    for (sym, a_child) in a_emap
+                    {
+                        if !b_emap.contains_key(sym)
+                        {
+                            diff.added.extend(after.leaf_paths(a_child));
+                        }
+                    }
+                },
+                (RellE::Exclusive(b_sid, b_child), RellE::Exclusive(a_sid, a_child)) =>
+                {
+                    if b_sid == a_sid
+                    {
+                        node_pairs.push((*b_child, *a_child));
+                    }
+                    else
+                    {
+                        // Adding the new value drops the old one, and whatever was under it
+                        let new_path = after.get_path(a_child).unwrap();
+                        diff.added.extend(after.leaf_paths(a_child).into_iter().filter(|p| *p != new_path));
+                        diff.replaced.push((before.get_path(b_child).unwrap(), new_path));
+                    }
+                },
+                (_, RellE::Empty) =>
+                {
+                    for b_child in b_node.edge.children()
+                    {
+                        diff.removed.push(before.get_path(&b_child).unwrap());
+                    }
+                },
+                (RellE::Empty, _) =>
+                {
+                    for a_child in a_node.edge.children()
+                    {
+                        diff.added.extend(after.leaf_paths(&a_child));
+                    }
+                },
+                (_, _) =>
+                {
+                    // Edge changed between exclusive and non-exclusive, that can't be upgraded
+                    // in place so the node goes and comes back with its new children
+                    diff.removed.push(before.get_path(&b_nid).unwrap());
+                    diff.added.extend(after.leaf_paths(&a_nid));
+                }
+            }
+        }
+
+        diff
+    }
+
+    pub fn apply_patch(&mut self, diff: &TreeDiff) -> Result<()>
+    {
+        for path in &diff.removed
+        {
+            self.remove_statement(path)?;
+        }
+        for (_, to) in &diff.replaced
+        {
+            self.add_statement(to)?;
+        }
+        for path in &diff.added
+        {
+            self.add_statement(path)?;
+        }
+        Ok(())
+    }
+
+    // Statements for every leaf under (and including) nid
+    fn leaf_paths(&self, nid: &NID) -> Vec<String>
+    {
+        let mut paths = vec![];
+        let mut to_visit = vec![*nid];
+        while let Some(nid) = to_visit.pop()
+        {
+            let children = self.nodes.get(&nid).unwrap().edge.children();
+            if children.is_empty()
+            {
+                paths.push(self.get_path(&nid).unwrap());
+            }
+            to_visit.extend(children.into_iter().rev());
+        }
+        paths
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::runtime::RellRuntime;
+
+    fn tree(statements: &[&str]) -> Result<RellTree>
+    {
+        RellTree::from_statements(statements)
+    }
+
+    #[test]
+    fn diff() -> Result<()>
+    {
+        let before = tree(&["goat.in!left", "man.in!left.bank", "man.knows.goat", "wolf.is!hungry", "cabbage.is.green"])?;
+        let after = tree(&["goat.in!right", "man.in!right", "man.knows.goat", "man.knows.wolf.well", "wolf.is.hungry", "boat.is!empty"])?;
+
+        let diff = RellTree::diff(&before, &after);
+        let mut replaced = diff.replaced.clone();
+        replaced.sort();
+        assert_eq!(replaced, vec![("goat.in!left".to_string(), "goat.in!right".to_string()),
+                                  ("man.in!left".to_string(), "man.in!right".to_string())]);
+
+        let mut removed = diff.removed.clone();
+        removed.sort();
+        assert_eq!(removed, vec!["cabbage", "wolf.is"]);
+
+        let mut added = diff.added.clone();
+        added.sort();
+        assert_eq!(added, vec!["boat.is!empty", "man.knows.wolf.well", "wolf.is.hungry"]);
+
+        let mut patched = tree(&["goat.in!left", "man.in!left.bank", "man.knows.goat", "wolf.is!hungry", "cabbage.is.green"])?;
+        patched.apply_patch(&diff)?;
+        assert_eq!(patched, after, "{}\n{}", patched, after);
+        assert!(RellTree::diff(&patched, &after).is_empty());
+
+        // Backwards
+        let mut unpatched = RellTree::from_statements(after.to_statements())?;
+        unpatched.apply_patch(&RellTree::diff(&after, &before))?;
+        assert_eq!(unpatched, before);
+
+        assert!(RellTree::diff(&before, &before).is_empty());
+        assert_eq!(format!("{}", RellTree::diff(&tree(&["a!b"])?, &tree(&["a!c", "d"])?)), "~ a!b -> a!c\n+ d\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn diff_runtime_update() -> Result<()>
+    {
+        let mut runtime = RellRuntime::new(tree(&["goat.in!left", "goat.wants.cabbage", "cabbage.in!left"])?);
+        runtime.add_rule("eat", crate::parser::RellParser::parse_rule("X.wants.Y, X.in!Z, Y.in!Z => -Y.in, Y.is!eaten")?)?;
+
+        let before = RellTree::from_statements(runtime.world_tree().to_statements())?;
+        runtime.update()?;
+        let diff = RellTree::diff(&before, runtime.world_tree());
+        assert_eq!(diff, TreeDiff { removed: vec!["cabbage.in".to_string()],
+                                    replaced: vec![],
+                                    added: vec!["cabbage.is!eaten".to_string()] });
+
+        Ok(())
+    }
+}
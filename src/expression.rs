@@ -0,0 +1,318 @@
+use crate::rellcore::*;
+use crate::rellcore::errors::*;
+use crate::parser::RellParser;
+use crate::symbols::SymbolsTable;
+
+// Arithmetic over numeric symbols, used for computed posteriors such as `X.hp!(H - 1)`
+//   expr   := term (('+' | '-') term)*
+//   term   := factor (('*' | '/') factor)*
+//   factor := '-' factor | '(' expr ')' | number | Variable
+pub struct Expression<'a>
+{
+    expression: &'a str,
+    chars: Vec<char>,
+    pos: usize,
+    symbols: &'a SymbolsTable,
+}
+
+impl<'a> Expression<'a>
+{
+    pub const OPEN: char = '(';
+    pub const CLOSE: char = ')';
+
+    // Variables are looked up through the symbol table, so they have to be bound already
+    pub fn evaluate(expression: &'a str, symbols: &'a SymbolsTable) -> Result<RellSymValue>
+    {
+        let mut expr = Self { expression, chars: expression.chars().collect(), pos: 0, symbols };
+        let val = expr.parse_expr()?;
+        expr.skip_whitespace();
+        match expr.peek()
+        {
+            None => Ok(val),
+            Some(c) => Err(expr.error(format!("unexpected '{}'", c))),
+        }
+    }
+
+    // Byte ranges of the parenthesized expressions in a statement, including the parens. Computed
+    // symbols replace a node value, so each one has to follow an exclusive edge. Plain numbers
+    // such as `.(2.5)` are just symbols and can go anywhere
+    pub fn find_all(statement: &str) -> Result<Vec<(usize, usize)>>
+    {
+        let malformed = |reason: &str| Error::MalformedRule { rule: statement.to_string(), reason: reason.to_string() };
+
+        let mut found = vec![];
+        let mut depth = 0;
+        let mut start = 0;
+        let mut quoted = false;
+        let mut escaped = false;
+        for (i, c) in statement.char_indices()
+        {
+            if escaped
+            {
+                escaped = false;
+                continue;
+            }
+
+            match c
+            {
+                '\\' if quoted => escaped = true,
+                RellParser::QUOTE if depth == 0 => quoted = !quoted,
+                _ if quoted => {},
+                Self::OPEN =>
+                {
+                    if depth == 0
+                    {
+                        start = i;
+                    }
+                    depth += 1;
+                },
+                Self::CLOSE =>
+                {
+                    if depth == 0
+                    {
+                        return Err(malformed("unbalanced `)`"));
+                    }
+                    depth -= 1;
+                    if depth == 0
+                    {
+                        if statement[..start].ends_with('!')
+                        {
+                            found.push((start, i + 1));
+                        }
+                        else if !matches!(RellParser::parse_numeric(&statement[start..i + 1]), Some(Ok(_)))
+                        {
+                            return Err(malformed("computed symbols must follow an exclusive edge `!`"));
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        if depth != 0
+        {
+            return Err(malformed("unbalanced `(`"));
+        }
+        Ok(found)
+    }
+
+    // Replaces every `(expression)` in the statement by its value
+    pub fn substitute(statement: &str, symbols: &SymbolsTable) -> Result<String>
+    {
+        let mut substituted = String::new();
+        let mut last = 0;
+        for (start, end) in Self::find_all(statement)?
+        {
+            let val = Expression::evaluate(&statement[start + 1..end - 1], symbols)?;
+            substituted.push_str(&statement[last..start]);
+            substituted.push_str(&RellSym::new(val).to_string());
+            last = end;
+        }
+        substituted.push_str(&statement[last..]);
+        Ok(substituted)
+    }
+
+    fn error(&self, reason: String) -> Error
+    {
+        Error::Arithmetic { expression: self.expression.to_string(), reason }
+    }
+
+    fn peek(&self) -> Option<char>
+    {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self)
+    {
+        while self.peek().is_some_and(char::is_whitespace)
+        {
+            self.pos += 1;
+        }
+    }
+
+    // Skips whitespace and consumes c if it comes next
+    fn eat(&mut self, c: char) -> bool
+    {
+        self.skip_whitespace();
+        let found = self.peek() == Some(c);
+        if found
+        {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn parse_expr(&mut self) -> Result<RellSymValue>
+    {
+        let mut val = self.parse_term()?;
+        loop
+        {
+            if self.eat('+')
+            {
+                let rhs = self.parse_term()?;
+                val = self.apply('+', val, rhs)?;
+            }
+            else if self.eat('-')
+            {
+                let rhs = self.parse_term()?;
+                val = self.apply('-', val, rhs)?;
+            }
+            else
+            {
+                return Ok(val);
+            }
+        }
+    }
+
+    fn parse_term(&mut self) -> Result<RellSymValue>
+    {
+        let mut val = self.parse_factor()?;
+        loop
+        {
+            if self.eat('*')
+            {
+                let rhs = self.parse_factor()?;
+                val = self.apply('*', val, rhs)?;
+            }
+            else if self.eat('/')
+            {
+                let rhs = self.parse_factor()?;
+                val = self.apply('/', val, rhs)?;
+            }
+            else
+            {
+                return Ok(val);
+            }
+        }
+    }
+
+    fn parse_factor(&mut self) -> Result<RellSymValue>
+    {
+        if self.eat('-')
+        {
+            let val = self.parse_factor()?;
+            return self.apply('-', RellSymValue::Integer(0), val);
+        }
+
+        if self.eat(Self::OPEN)
+        {
+            let val = self.parse_expr()?;
+            if !self.eat(Self::CLOSE)
+            {
+                return Err(self.error("missing `)`".to_string()));
+            }
+            return Ok(val);
+        }
+
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '.')
+        {
+            self.pos += 1;
+        }
+        let operand: String = self.chars[start..self.pos].iter().collect();
+
+        match operand.chars().next()
+        {
+            None => Err(self.error(match self.peek()
+            {
+                Some(c) => format!("unexpected '{}'", c),
+                None => "unexpected end".to_string(),
+            })),
+            Some('0'..='9') => match operand.parse::<i64>()
+            {
+                Ok(i) => Ok(RellSymValue::Integer(i)),
+                Err(_) => operand.parse::<f64>().map(RellSymValue::from_f64)
+                                                .map_err(|e| self.error(format!("invalid number `{}` ({})", operand, e))),
+            },
+            Some(_) =>
+            {
+                let sid = self.symbols.get_sid_no_binding(&operand);
+                match self.symbols.get_sym(&sid).map(|sym| sym.get_val())
+                {
+                    Some(val) if val.is_numeric() => Ok(val.clone()),
+                    Some(RellSymValue::Identifier(_)) | None => Err(self.error(format!("`{}` is not bound", operand))),
+                    Some(_) => Err(self.error(format!("`{}` is not a number", operand))),
+                }
+            },
+        }
+    }
+
+    // Integers stay integers unless the result doesn't fit or isn't whole
+    fn apply(&self, op: char, lhs: RellSymValue, rhs: RellSymValue) -> Result<RellSymValue>
+    {
+        if let (RellSymValue::Integer(a), RellSymValue::Integer(b)) = (&lhs, &rhs)
+        {
+            let exact = match op
+            {
+                '+' => a.checked_add(*b),
+                '-' => a.checked_sub(*b),
+                '*' => a.checked_mul(*b),
+                _   => if a.checked_rem(*b) == Some(0) { a.checked_div(*b) } else { None },
+            };
+            if let Some(i) = exact
+            {
+                return Ok(RellSymValue::Integer(i));
+            }
+        }
+
+        let (a, b) = (lhs.as_f64().unwrap(), rhs.as_f64().unwrap());
+        if op == '/' && b == 0.0
+        {
+            return Err(self.error("division by zero".to_string()));
+        }
+
+        let result = match op
+        {
+            '+' => a + b,
+            '-' => a - b,
+            '*' => a * b,
+            _   => a / b,
+        };
+        if !result.is_finite()
+        {
+            return Err(self.error("result out of range".to_string()));
+        }
+        Ok(RellSymValue::from_f64(result))
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::tree::RellTree;
+
+    #[test]
+    fn evaluate() -> Result<()>
+    {
+        let mut t = RellTree::new();
+        t.add_statement("a.H")?;
+        let eval = |e: &str, t: &RellTree| Expression::evaluate(e, &t.symbols);
+
+        assert!(matches!(eval("1 + 2 * 3", &t)?, RellSymValue::Integer(7)));
+        assert!(matches!(eval("(1 + 2) * 3", &t)?, RellSymValue::Integer(9)));
+        assert!(matches!(eval("7 / 2", &t)?, RellSymValue::Float(n) if n == 3.5));
+        assert!(matches!(eval("6 / 2", &t)?, RellSymValue::Integer(3)));
+        assert!(matches!(eval("0.5 + 0.5", &t)?, RellSymValue::Integer(1)));
+        assert!(matches!(eval("-2 - -3", &t)?, RellSymValue::Integer(1)));
+        assert!(matches!(eval("1 / 0", &t), Err(Error::Arithmetic { .. })));
+        assert!(matches!(eval("1 +", &t), Err(Error::Arithmetic { .. })));
+        assert!(matches!(eval("(1", &t), Err(Error::Arithmetic { .. })));
+        assert!(matches!(eval("H + 1", &t), Err(Error::Arithmetic { .. })), "Unbound variable");
+
+        let mut vars = std::collections::BTreeMap::new();
+        t.add_statement("b!41")?;
+        vars.insert(t.symbols.get_sid_no_binding("H"), t.symbols.get_sid("41"));
+        t.symbols.bind_variables(&mut vars);
+        assert!(matches!(eval("H + 1", &t)?, RellSymValue::Integer(42)));
+        assert_eq!(Expression::substitute("a.hp!(H - 50)", &t.symbols)?, "a.hp!-9");
+        assert_eq!(Expression::substitute("a.name!\"(not math)\"", &t.symbols)?, "a.name!\"(not math)\"");
+
+        assert!(Expression::find_all("a.hp.(H - 1)").is_err());
+        assert!(Expression::find_all("a.hp!(H - 1").is_err());
+        assert_eq!(Expression::find_all("a.b!((1 + 2) * 3)")?, vec![(4, 17)]);
+        assert_eq!(Expression::find_all("a.b.(2.5)")?, vec![], "Plain numbers aren't computed");
+
+        Ok(())
+    }
+}
@@ -1,5 +1,5 @@
-#![cfg_attr(feature = "cargo-clippy", allow(clippy::suspicious_else_formatting))]
-#![cfg_attr(feature = "cargo-clippy", allow(clippy::trivially_copy_pass_by_ref))]
+#![allow(clippy::suspicious_else_formatting)]
+#![allow(clippy::trivially_copy_pass_by_ref)]
 
 #[macro_use]
 extern crate log;
@@ -13,11 +13,14 @@ pub mod tree;
 use crate::tree::*;
 
 pub mod tree_traits;
+pub mod diff;
 
 pub mod binding;
+pub mod expression;
 pub mod logic;
 pub mod query;
 pub mod symbols;
+pub mod trace;
 
 use crate::logic::*;
 use crate::rellcore::errors::*;
@@ -25,34 +28,178 @@ use crate::rellcore::errors::*;
 pub mod runtime
 {
     use super::*;
+    use crate::parser::{ RellParser, Definition };
+    use std::io::{ BufRead, BufReader, Read };
+    use std::path::Path;
 
     pub struct RellRuntime
     {
-        rules: Vec<implications::BindableImplication>,
+        rules: Vec<(String, implications::BindableImplication)>, // Applied in insertion order
+        functions: Vec<(String, RellFunction)>,
         world_tree: RellTree,
     }
 
     impl RellRuntime
     {
+        pub fn new(world_tree: RellTree) -> Self
+        {
+            Self { rules: vec![], functions: vec![], world_tree }
+        }
+
+        // Loads facts, rules and functions, one definition per line (see RellParser::parse_definition)
+        pub fn load<R>(&mut self, reader: R) -> Result<()>
+            where R: Read
+        {
+            for (i, line) in BufReader::new(reader).lines().enumerate()
+            {
+                let at_line = |e| Error::AtLine { line: i + 1, source: Box::new(e) };
+                if let Some(definition) = RellParser::parse_definition(line?).map_err(at_line)?
+                {
+                    self.add_definition(definition).map_err(at_line)?;
+                }
+            }
+            Ok(())
+        }
+
+        pub fn load_file<P>(&mut self, path: P) -> Result<()>
+            where P: AsRef<Path>
+        {
+            self.load(std::fs::File::open(path)?)
+        }
+
+        pub fn add_definition(&mut self, definition: Definition) -> Result<()>
+        {
+            match definition
+            {
+                Definition::Fact(statement) =>
+                {
+                    self.world_tree.add_statement(statement)?;
+                },
+                Definition::Rule { name, priors, posteriors } =>
+                {
+                    let name = name.unwrap_or_else(|| {
+                        (self.rules.len() + 1..).map(|i| format!("rule_{}", i)).find(|n| self.get_rule(n).is_none()).unwrap()
+                    });
+                    self.add_rule(name, implications::BindableImplication::from_statements(priors, posteriors)?)?;
+                },
+                Definition::Function { name, signature, priors, posteriors } =>
+                {
+                    self.add_function(name, RellFunction::from_statements(signature, priors, posteriors)?)?;
+                },
+            }
+            Ok(())
+        }
+
+        pub fn add_function<S>(&mut self, name: S, function: RellFunction) -> Result<()>
+            where S: AsRef<str>
+        {
+            let name = name.as_ref();
+            if self.functions.iter().any(|(function_name, _)| function_name == name)
+            {
+                return Err(Error::DuplicateFunction(name.to_string()));
+            }
+            self.functions.push((name.to_string(), function));
+            Ok(())
+        }
+
+        pub fn remove_function<S>(&mut self, name: S) -> Option<RellFunction>
+            where S: AsRef<str>
+        {
+            let i = self.functions.iter().position(|(function_name, _)| function_name == name.as_ref())?;
+            Some(self.functions.remove(i).1)
+        }
+
+        pub fn function_names(&self) -> Vec<&str>
+        {
+            self.functions.iter().map(|(name, _)| name.as_str()).collect()
+        }
+
+        pub fn call_function<S, C>(&mut self, name: S, call_statement: C) -> Result<()>
+            where S: AsRef<str>, C: AsRef<str>
+        {
+            let name = name.as_ref();
+            let world_tree = &mut self.world_tree;
+            match self.functions.iter_mut().find(|(function_name, _)| function_name == name)
+            {
+                Some((_, function)) => function.call_func_on(world_tree, call_statement),
+                None => Err(Error::UnknownFunction(name.to_string())),
+            }
+        }
+
+        pub fn add_rule<S>(&mut self, name: S, rule: implications::BindableImplication) -> Result<()>
+            where S: AsRef<str>
+        {
+            let name = name.as_ref();
+            if self.get_rule(name).is_some()
+            {
+                return Err(Error::DuplicateRule(name.to_string()));
+            }
+            self.rules.push((name.to_string(), rule));
+            Ok(())
+        }
+
+        pub fn remove_rule<S>(&mut self, name: S) -> Option<implications::BindableImplication>
+            where S: AsRef<str>
+        {
+            let i = self.rules.iter().position(|(rule_name, _)| rule_name == name.as_ref())?;
+            Some(self.rules.remove(i).1)
+        }
+
+        pub fn get_rule<S>(&self, name: S) -> Option<&implications::BindableImplication>
+            where S: AsRef<str>
+        {
+            self.rules.iter().find(|(rule_name, _)| rule_name == name.as_ref()).map(|(_, rule)| rule)
+        }
+
+        pub fn get_rule_mut<S>(&mut self, name: S) -> Option<&mut implications::BindableImplication>
+            where S: AsRef<str>
+        {
+            self.rules.iter_mut().find(|(rule_name, _)| rule_name == name.as_ref()).map(|(_, rule)| rule)
+        }
+
+        pub fn rule_names(&self) -> Vec<&str>
+        {
+            self.rules.iter().map(|(name, _)| name.as_str()).collect()
+        }
+
+        pub fn world_tree(&self) -> &RellTree
+        {
+            &self.world_tree
+        }
+
+        pub fn world_tree_mut(&mut self) -> &mut RellTree
+        {
+            &mut self.world_tree
+        }
+
+        pub fn into_world_tree(self) -> RellTree
+        {
+            self.world_tree
+        }
+
+        // Rules like `X.hp!H => X.hp!(H - 1)` change the tree every time they apply, so they
+        // never settle without a guard such as `H > 0`
+        pub const MAX_UPDATE_STEPS: usize = 10_000;
+
         pub fn update(&mut self) -> Result<()>
         {
-            loop
+            for _ in 0..Self::MAX_UPDATE_STEPS
             {
                 debug!("Update Loop Starting");
                 if !self.step()?
                 {
                     debug!("Update Loop Ending");
-                    break;
+                    return Ok(());
                 }
             }
-            
-            Ok(())
+
+            Err(Error::UpdateLimit(Self::MAX_UPDATE_STEPS))
         }
 
         pub fn step(&mut self) -> Result<bool>
         {
             let mut need_update = false;
-            for implication in &mut self.rules
+            for (_, implication) in &mut self.rules
             {
                 let r = implication.apply(&mut self.world_tree)?;
                 need_update |= r;
@@ -61,6 +208,14 @@ pub mod runtime
         }
     }
 
+    impl Default for RellRuntime
+    {
+        fn default() -> Self
+        {
+            Self::new(RellTree::new())
+        }
+    }
+
     pub struct RellFunction
     {
         binding_state: implications::BindableImplication
@@ -73,18 +228,20 @@ pub mod runtime
         {
             let mut prereqs = prereqs.clone();
             prereqs.push(function_signature);
-            Ok(Self { binding_state: implications::BindableImplication::from_statements(prereqs, postconditions).unwrap() })
+            Ok(Self { binding_state: implications::BindableImplication::from_statements(prereqs, postconditions)? })
         }
 
         pub fn call_func_on<S>(&mut self, w: &mut RellTree, call_statement: S) -> Result<()> where S: AsRef<str>
         {
-            w.add_statement(call_statement)?;
-            if self.binding_state.apply(w)?
-            {
-                println!("Function call successful");
-            }
-            w.add_statement("func!empty")?; // Dont really like this
-            Ok(())
+            let call_statement = call_statement.as_ref();
+            w.transaction(|w| {
+                w.add_statement(call_statement)?;
+                let triggered = self.binding_state.apply(w)?;
+                debug!("Function call {} triggered: {}", call_statement, triggered);
+                crate::trace::emit(|| crate::trace::TraceEvent::FunctionCalled { call: call_statement.to_string(), triggered });
+                w.add_statement("func!empty")?; // Dont really like this
+                Ok(())
+            })
         }
     }
 
@@ -103,6 +260,12 @@ pub mod runtime
             // Calling
             f.call_func_on(&mut w, "func!move.goat.to.left")?;
             assert!(w.get_at_path("goat.in.left").is_some());
+
+            // A failing call leaves the world untouched, call statement included
+            let mut f = RellFunction::from_statements("func!paint.X", vec!["X.in.Y"], vec!["X.color!red", "X.in!nowhere"]).unwrap();
+            let before = w.nodes.clone();
+            assert!(f.call_func_on(&mut w, "func!paint.goat").is_err());
+            assert_eq!(w.nodes, before);
             Ok(())
         }
     }
@@ -130,13 +293,101 @@ pub mod runtime
                                 vec!["X.in.Y", "Y.in.Z"],    // Implication Priors
                                 vec!["X.in.Z"])?;            // Posteriors
 
-            let mut rr = RellRuntime { rules: vec![imp], world_tree: w }; 
+            let mut rr = RellRuntime::new(w);
+            rr.add_rule("transitive_in", imp)?;
+
+            rr.update()?;
+
+            assert!(rr.world_tree().get_at_path("place.in.state").is_some());   // Assert Posterior of Implication
+            assert!(rr.world_tree().get_at_path("city.in.country").is_some());   // Assert Posterior of Implication
+            assert!(rr.world_tree().get_at_path("place.in.country").is_some());   // Assert Posterior of Implication
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_rule_management() -> Result<()>
+        {
+            let mut rr = RellRuntime::default();
+            rr.world_tree_mut().add_statement("city.in.state")?;
+            rr.world_tree_mut().add_statement("state.in.country")?;
+
+            rr.add_rule("transitive_in", implications::BindableImplication::from_statements(vec!["X.in.Y", "Y.in.Z"], vec!["X.in.Z"])?)?;
+            rr.add_rule("located", implications::BindableImplication::from_statements(vec!["X.in.country"], vec!["X.is.located"])?)?;
+            assert!(rr.add_rule("located", implications::BindableImplication::from_statements(vec!["X.in.Y"], vec!["X.is.located"])?).is_err());
+            assert_eq!(rr.rule_names(), vec!["transitive_in", "located"]);
+
+            assert!(rr.remove_rule("transitive_in").is_some());
+            assert!(rr.remove_rule("transitive_in").is_none());
+            assert!(rr.get_rule("transitive_in").is_none());
+            assert!(rr.get_rule("located").is_some());
 
             rr.update()?;
+            assert!(rr.world_tree().get_at_path("city.in.country").is_none(), "Removed rule should not be applied");
+            assert!(rr.world_tree().get_at_path("state.is.located").is_some());
+            assert!(rr.world_tree().get_at_path("city.is.located").is_none());
+
+            let w = rr.into_world_tree();
+            assert!(w.get_at_path("state.is.located").is_some());
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_computed_update() -> Result<()>
+        {
+            let mut rr = RellRuntime::default();
+            rr.load("knight.hp!3\nrabbit.hp!(1.5)\nrule bleed: X.hp!H, H > 0 => X.hp!(H - 1)".as_bytes())?;
+            rr.update()?;
+            assert!(rr.world_tree().get_at_path("knight.hp!0").is_some());
+            assert!(rr.world_tree().get_at_path("rabbit.hp!(-0.5)").is_some());
+
+            // Without the guard it never stops
+            rr.add_rule("tick", RellParser::parse_rule("X.hp!H => X.hp!(H - 1)")?)?;
+            assert!(matches!(rr.update(), Err(Error::UpdateLimit(RellRuntime::MAX_UPDATE_STEPS))));
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_load() -> Result<()>
+        {
+            let _ = env_logger::builder().is_test(true).try_init();
+
+            let world = "\
+                # Initial state
+                goat.in!left
+                cabagge.in!left
+                man.in!left
+
+                fn move: func!move.X.to.Y, X.in!Z => X.in!Y
+                fn grab: func!grab.Q.T, Q.in!H, T.in!H => Q.holds!T
+
+                # Moving while holding something should move the thing
+                man.holds!O, man.in!P, O.in!D => O.in!P
+                rule goat_eats_cabbage: goat.in!X, cabagge.in!X, not man.in!X => cabagge.is!eaten
+            ";
+
+            let mut rr = RellRuntime::default();
+            rr.load(world.as_bytes())?;
+
+            assert_eq!(rr.rule_names(), vec!["rule_1", "goat_eats_cabbage"]);
+            assert_eq!(rr.function_names(), vec!["move", "grab"]);
+            assert!(rr.world_tree().get_at_path("man.in!left").is_some());
+
+            rr.call_function("grab", "func!grab.man.goat")?;
+            rr.call_function("move", "func!move.man.to.right")?;
+            rr.update()?;
+
+            assert!(rr.world_tree().get_at_path("goat.in!right").is_some());
+            assert!(rr.world_tree().get_at_path("cabagge.is!eaten").is_none());
+            assert!(matches!(rr.call_function("fly", "func!fly.man"), Err(Error::UnknownFunction(_))));
+
+            let err = RellRuntime::default().load("goat.in!left\ngoat.in.left\n".as_bytes());
+            assert!(matches!(err, Err(Error::AtLine { line: 2, .. })), "Result is: {:?}", err);
 
-            assert!(rr.world_tree.get_at_path("place.in.state").is_some());   // Assert Posterior of Implication
-            assert!(rr.world_tree.get_at_path("city.in.country").is_some());   // Assert Posterior of Implication
-            assert!(rr.world_tree.get_at_path("place.in.country").is_some());   // Assert Posterior of Implication
+            let err = RellRuntime::default().load("rule a: X.in.Y => X.is.in\nrule a: X.in.Y => X.is.in".as_bytes());
+            assert!(matches!(err, Err(Error::AtLine { line: 2, .. })), "Result is: {:?}", err);
 
             Ok(())
         }
@@ -173,43 +424,46 @@ pub mod runtime
                                 vec!["man.holds!O", "man.in!P", "O.in!D"],
                                 vec!["O.in!P"])?;
 
-            let mut rr = RellRuntime { rules: vec![mov_imp, goat_imp, dog_imp], world_tree: w }; 
+            let mut rr = RellRuntime::new(w);
+            rr.add_rule("move_held", mov_imp)?;
+            rr.add_rule("goat_eats_cabbage", goat_imp)?;
+            rr.add_rule("dog_eats_goat", dog_imp)?;
             rr.update()?;
 
             // Everyone is A-OK
-            assert!(rr.world_tree.get_at_path("goat.is!eaten").is_none());
-            assert!(rr.world_tree.get_at_path("cabagge.is!eaten").is_none());
+            assert!(rr.world_tree().get_at_path("goat.is!eaten").is_none());
+            assert!(rr.world_tree().get_at_path("cabagge.is!eaten").is_none());
 
             // Man grabs goat and moves to the right
-            grab_f.call_func_on(&mut rr.world_tree, "func!grab.man.goat")?;
-            move_f.call_func_on(&mut rr.world_tree, "func!move.man.to.right")?;
+            grab_f.call_func_on(rr.world_tree_mut(), "func!grab.man.goat")?;
+            move_f.call_func_on(rr.world_tree_mut(), "func!move.man.to.right")?;
 
             rr.update()?;
 
             // Goat is right
-            assert!(rr.world_tree.get_at_path("goat.in!right").is_some());
+            assert!(rr.world_tree().get_at_path("goat.in!right").is_some());
             // Everyone is alive
-            assert!(rr.world_tree.get_at_path("goat.is!eaten").is_none());
-            assert!(rr.world_tree.get_at_path("cabagge.is!eaten").is_none());
+            assert!(rr.world_tree().get_at_path("goat.is!eaten").is_none());
+            assert!(rr.world_tree().get_at_path("cabagge.is!eaten").is_none());
 
             // Man moves back... Still holds goat 
-            move_f.call_func_on(&mut rr.world_tree, "func!move.man.to.left")?;
+            move_f.call_func_on(rr.world_tree_mut(), "func!move.man.to.left")?;
 
             rr.update()?;
 
             // Goat back left
-            assert!(rr.world_tree.get_at_path("goat.in!left").is_some());
+            assert!(rr.world_tree().get_at_path("goat.in!left").is_some());
 
             // Grab cabbage and move
-            grab_f.call_func_on(&mut rr.world_tree, "func!grab.man.cabagge")?;
-            move_f.call_func_on(&mut rr.world_tree, "func!move.man.to.right")?;
+            grab_f.call_func_on(rr.world_tree_mut(), "func!grab.man.cabagge")?;
+            move_f.call_func_on(rr.world_tree_mut(), "func!move.man.to.right")?;
 
             rr.update()?;
 
             // Cabbage is right
-            assert!(rr.world_tree.get_at_path("cabagge.in!right").is_some());
+            assert!(rr.world_tree().get_at_path("cabagge.in!right").is_some());
             // Goat is dead :( 
-            assert!(rr.world_tree.get_at_path("goat.is!eaten").is_some());
+            assert!(rr.world_tree().get_at_path("goat.is!eaten").is_some());
 
 
             Ok(())
@@ -1,6 +1,7 @@
 use crate::rellcore::errors::*;
 use crate::tree::*;
 use crate::binding::*;
+use crate::expression::Expression;
 
 pub mod implications
 {
@@ -34,7 +35,7 @@ pub mod implications
 
         pub fn apply(&self, tree: &mut RellTree) -> bool
         {
-            if *tree < self.prior
+            if *tree <= self.prior
             {
                 *tree = tree.greatest_lower_bound(&self.posterior).unwrap();
                 true
@@ -56,6 +57,27 @@ pub mod implications
 
     impl BindableImplication
     {
+        // Posteriors starting with either of these remove the statement (and everything under it)
+        pub const RETRACTION_PREFIX: &'static str = "-";
+        pub const DELETE_KEYWORD: &'static str = "delete ";
+
+        // (is it a retraction, statement)
+        pub fn split_retraction(posterior: &str) -> (bool, &str)
+        {
+            let posterior = posterior.trim();
+            if let Some(statement) = posterior.strip_prefix(Self::DELETE_KEYWORD)
+            {
+                return (true, statement.trim());
+            }
+
+            match posterior.strip_prefix(Self::RETRACTION_PREFIX)
+            {
+                // -5.x is a statement about a negative number
+                Some(statement) if !statement.starts_with(|c: char| c.is_ascii_digit()) => (true, statement.trim()),
+                _ => (false, posterior),
+            }
+        }
+
         pub fn from_statements<S>(priors: Vec<S>, posteriors: Vec<S>) -> Result<Self>
           where S: AsRef<str>
         {
@@ -63,32 +85,56 @@ pub mod implications
 
             for prior in priors
             {
-                binding_state.add_statement(prior);
+                binding_state.add_prior(prior);
             }
 
-            let posteriors = posteriors.iter().map( | s | s.as_ref().to_string() ).collect();
+            let posteriors: Vec<String> = posteriors.iter().map( | s | s.as_ref().to_string() ).collect();
+            for posterior in &posteriors
+            {
+                Expression::find_all(Self::split_retraction(posterior).1)?;
+            }
 
             Ok(Self { binding_state, posteriors })
         }
 
+        // See BindingState::allow_shared_values
+        pub fn allow_shared_values(mut self, allow: bool) -> Self
+        {
+            self.binding_state.allow_shared_values(allow);
+            self
+        }
+
         pub fn apply(&mut self, tree: &mut RellTree) -> Result<bool>
         {
-            let mut compat_bindings = self.binding_state.generate_compatible_on(tree);
+            let mut compat_bindings = self.binding_state.generate_compatible_on(tree)?;
 
             debug!("Compatible Bindings Found: {}", compat_bindings.len());
             debug!("Compatible Bindings: {:?}", compat_bindings);
-            let mut added = 0;
-            for compat_binding in &mut compat_bindings
-            {
-                tree.symbols.bind_variables(compat_binding);
-                for posterior in &self.posteriors
+            let posteriors = &self.posteriors;
+            // All or nothing, a failing posterior leaves the tree as it was
+            let changed = tree.transaction(|tree| {
+                let mut changed = 0;
+                for compat_binding in &mut compat_bindings
                 {
-                    let added_nids = tree.add_statement(posterior)?;
-                    added += added_nids.len();
+                    tree.symbols.bind_variables(compat_binding);
+                    for posterior in posteriors
+                    {
+                        let (retraction, statement) = Self::split_retraction(posterior);
+                        // Computed symbols need the bindings, evaluate before adding
+                        let changed_nids = Expression::substitute(statement, &tree.symbols).and_then(|s| {
+                            if retraction { tree.remove_statement(s) } else { tree.add_statement(s) }
+                        });
+                        match changed_nids
+                        {
+                            Ok(changed_nids) => changed += changed_nids.len(),
+                            Err(e) => { tree.symbols.clear_bindings(); return Err(e) },
+                        };
+                    }
+                    tree.symbols.clear_bindings();
                 }
-                tree.symbols.clear_bindings();
-            }
-            Ok(added > 0)
+                Ok(changed)
+            })?;
+            Ok(changed > 0)
         }
 
     }
@@ -99,6 +145,7 @@ mod test
 {
     use super::*;
     use super::implications::*;
+    use crate::rellcore::{ RellSymValue, SIDGenerator };
 
     #[test]
     fn test_apply() -> Result<()>
@@ -115,8 +162,8 @@ mod test
         "ROOT\n\
          -city\n\
          --in\n\
-         ---country\n\
          ---state\n\
+         ---country\n\
          -state\n\
          --in\n\
          ---country\n");
@@ -132,8 +179,8 @@ mod test
          --is\n\
          ---countrian\n\
          --in\n\
-         ---country\n\
          ---state\n\
+         ---country\n\
          -state\n\
          --in\n\
          ---country\n");
@@ -159,4 +206,183 @@ mod test
 
         Ok(())
     }
+
+    #[test]
+    fn bindable_negation() -> Result<()>
+    {
+        let mut w = RellTree::new();
+        w.add_statement("goat.in!left")?;
+        w.add_statement("cabagge.in!left")?;
+        w.add_statement("man.in!left")?;
+
+        let mut imp = BindableImplication::from_statements(
+                            vec!["goat.in!X", "cabagge.in!X", "not man.in!X"],
+                            vec!["cabagge.is!eaten"])?;
+
+        assert!(matches!(imp.apply(&mut w), Ok(t) if !t), "Man is watching, nothing should happen");
+        assert!(w.get_at_path("cabagge.is!eaten").is_none());
+
+        w.add_statement("man.in!right")?;
+        assert!(matches!(imp.apply(&mut w), Ok(t) if t));
+        assert!(w.get_at_path("cabagge.is!eaten").is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn bindable_arithmetic() -> Result<()>
+    {
+        let mut w = RellTree::new();
+        w.add_statement("knight.gold!10")?;
+        w.add_statement("knight.earned!(2.5)")?;
+        w.add_statement("rabbit.hp!3")?;
+
+        let mut imp = BindableImplication::from_statements(vec!["X.gold!G", "X.earned!E"], vec!["X.gold!(G + E)"])?;
+        assert!(imp.apply(&mut w)?);
+        assert!(w.get_at_path("knight.gold!(12.5)").is_some());
+        assert!(w.get_at_path("knight.gold!10").is_none(), "Computed symbol should replace the old value");
+
+        let mut imp = BindableImplication::from_statements(vec!["X.hp!H", "H > 0"], vec!["X.hp!(H - 1)"])?;
+        while imp.apply(&mut w)? {}
+        assert!(w.get_at_path("rabbit.hp!0").is_some());
+
+        let mut imp = BindableImplication::from_statements(vec!["X.hp!H"], vec!["X.hp!(H / 0)"])?;
+        assert!(matches!(imp.apply(&mut w), Err(Error::Arithmetic { .. })));
+
+        // i64::MIN / -1 doesn't fit in an integer
+        let mut min = RellTree::new();
+        min.add_statement("a.hp!-9223372036854775808")?;
+        let mut imp = BindableImplication::from_statements(vec!["X.hp!H"], vec!["X.v!(H / -1)"])?;
+        assert!(imp.apply(&mut min)?);
+        assert!(min.get_at_path("a.v!(9223372036854775808)").is_some());
+        assert!(!min.in_transaction());
+        assert!(BindableImplication::from_statements(vec!["X.hp!H"], vec!["X.hp.(H - 1)"]).is_err());
+
+        // A bound number after a . is a path, and has to reload as one
+        let mut w = RellTree::new();
+        w.add_statement("x.n!5")?;
+        let mut imp = BindableImplication::from_statements(vec!["x.n!N"], vec!["grid.3.N", "grid.(3.5)"])?;
+        assert!(imp.apply(&mut w)?);
+        let mut saved = vec![];
+        w.save(&mut saved)?;
+        let reloaded = RellTree::load(&saved[..])?;
+        assert_eq!(reloaded, w);
+        assert!(reloaded.get_at_path("grid.3.5").is_some());
+        assert!(reloaded.get_at_path("grid.(3.5)").is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn bindable_aggregate() -> Result<()>
+    {
+        let mut w = RellTree::new();
+        w.add_statement("goat.in!boat")?;
+        w.add_statement("man.in!boat")?;
+
+        let mut imp = crate::parser::RellParser::parse_rule("count(X : X.in!boat, not X.is!anchor) > 2 => boat.is!sunk")?;
+        assert!(!imp.apply(&mut w)?);
+
+        w.add_statement("wolf.in!boat")?;
+        assert!(imp.apply(&mut w)?);
+        assert!(w.get_at_path("boat.is!sunk").is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn bindable_retraction() -> Result<()>
+    {
+        let mut w = RellTree::new();
+        w.add_statement("man.holds!cabbage")?;
+        w.add_statement("man.drops!cabbage")?;
+        w.add_statement("goat.status.hungry.very")?;
+
+        let mut drop = BindableImplication::from_statements(vec!["X.drops!O", "X.holds!O"],
+                                                            vec!["-X.holds!O", "delete X.drops", "O.on!floor"])?;
+        assert!(drop.apply(&mut w)?);
+        assert!(w.get_at_path("man.holds").is_some());
+        assert!(w.get_at_path("man.holds!cabbage").is_none());
+        assert!(w.get_at_path("man.drops").is_none());
+        assert!(w.get_at_path("cabbage.on!floor").is_some());
+        assert!(!drop.apply(&mut w)?, "Nothing left to drop");
+
+        // Removing something that isn't there is not a change
+        let mut fed = BindableImplication::from_statements(vec!["X.status.hungry"], vec!["-X.status", "-X.mood"])?;
+        assert!(fed.apply(&mut w)?);
+        assert!(w.get_at_path("goat.status").is_none());
+        assert!(!fed.apply(&mut w)?);
+
+        assert_eq!(BindableImplication::split_retraction("-5.is!negative"), (false, "-5.is!negative"));
+        assert_eq!(BindableImplication::split_retraction("- X.is"), (true, "X.is"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn bindable_rollback() -> Result<()>
+    {
+        let mut w = RellTree::new();
+        w.add_statement("goat.in!left")?;
+        w.add_statement("cabbage.in!left")?;
+        w.add_statement("goat.is.hungry")?;
+        let before = w.nodes.clone();
+
+        // The first binding's posteriors go in, the second binding hits an incompatible edge
+        let mut imp = BindableImplication::from_statements(vec!["X.in!left"], vec!["X.in!right", "X.is!moved"])?;
+        assert!(matches!(imp.apply(&mut w), Err(Error::IncompatibleEdge { .. })));
+        assert_eq!(w.nodes, before, "{}", w);
+        assert!(w.symbols.get_sym(&w.symbols.get_sid_no_binding("X")).is_none_or(|sym| sym.to_string() == "X"), "Bindings must be cleared");
+
+        Ok(())
+    }
+
+    #[test]
+    fn bindable_quoted_literals() -> Result<()>
+    {
+        // "50" is text, not the number 50
+        let mut w = RellTree::new();
+        w.add_statement("a.hp!50")?;
+        w.add_statement("b.name!\"50\"")?;
+        let mut statements = w.to_statements();
+        statements.sort();
+        assert_eq!(statements, vec!["a.hp!50", "b.name!\"50\""]);
+
+        let mut imp = BindableImplication::from_statements(vec!["X.hp!H", "H > 10"], vec!["X.is!strong"])?;
+        assert!(imp.apply(&mut w)?);
+        assert!(w.get_at_path("a.is!strong").is_some());
+
+        // "X" is text, not the variable X, even while X is bound
+        let mut w = RellTree::new();
+        w.add_statement("npc.name!\"X\"")?;
+        w.add_statement("npc.in.town")?;
+
+        let mut imp = BindableImplication::from_statements(vec!["X.in.Y"], vec!["X.is.placed", "X.called!\"X\""])?;
+        assert!(imp.apply(&mut w)?);
+        let mut statements = w.to_statements();
+        statements.sort();
+        assert_eq!(statements, vec!["npc.called!\"X\"", "npc.in.town", "npc.is.placed", "npc.name!\"X\""]);
+        let reloaded = RellTree::from_statements(w.to_statements())?;
+        assert_eq!(reloaded.to_statements(), w.to_statements());
+
+        let matches = crate::query::query("npc.name!N", &w)?;
+        assert_eq!(matches[0].get("N").unwrap().get_val(), &RellSymValue::Literal("X".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn bindable_comparison() -> Result<()>
+    {
+        let mut w = RellTree::new();
+        w.add_statement("knight.hp!50")?;
+        w.add_statement("rabbit.hp!3")?;
+
+        let mut imp = BindableImplication::from_statements(vec!["X.hp!H", "H < 10"], vec!["X.state!dying"])?;
+        assert!(imp.apply(&mut w)?);
+        assert!(w.get_at_path("rabbit.state!dying").is_some());
+        assert!(w.get_at_path("knight.state").is_none());
+
+        Ok(())
+    }
 }
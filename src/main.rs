@@ -0,0 +1,212 @@
+use std::fs::File;
+use std::io::{self, BufRead, Write};
+
+use rell::parser::RellParser;
+use rell::query::{ self, Aggregate, AggregateOp };
+use rell::rellcore::RellSym;
+use rell::rellcore::errors::*;
+use rell::runtime::RellRuntime;
+
+const HELP: &str = "\
+goat.in!left                  Add a fact
+X.in.Y, Y.in.Z => X.in.Z      Add a rule (also `rule <name>: ...` and `fn <name>: ...`)
+?X.in.Y, Y.in.country         Query, prints the bindings for every match
+?count(X : X.in!boat)         Aggregate, also sum, min, max and distinct
+:remove goat.in               Remove a statement and everything under it
+:rules                        List rules
+:functions                    List functions
+:call <name> <statement>      Call a function, e.g. :call move func!move.goat.to.left
+:step                         Apply every rule once
+:run                          Apply rules until nothing changes
+:show                         Print the world tree
+:load <file>                  Load facts, rules and functions from a .rell file
+:save <file>                  Save the world tree facts only, rules and functions are not saved
+:help                         Show this message
+:quit                         Exit";
+
+enum Outcome
+{
+    Continue(String),
+    Quit,
+}
+
+struct Repl
+{
+    runtime: RellRuntime,
+}
+
+impl Repl
+{
+    fn new() -> Self
+    {
+        Self { runtime: RellRuntime::default() }
+    }
+
+    fn eval(&mut self, line: &str) -> Result<Outcome>
+    {
+        let line = line.trim();
+        let output = if let Some(q) = line.strip_prefix('?')
+        {
+            self.query(q)?
+        }
+        else if let Some(command) = line.strip_prefix(':')
+        {
+            let (command, arg) = match command.split_once(char::is_whitespace)
+            {
+                Some((command, arg)) => (command, arg.trim()),
+                None                 => (command, ""),
+            };
+            match command
+            {
+                "quit" | "q" => return Ok(Outcome::Quit),
+                _ => self.command(command, arg)?,
+            }
+        }
+        else
+        {
+            match RellParser::parse_definition(line)?
+            {
+                Some(definition) => { self.runtime.add_definition(definition)?; "ok".to_string() },
+                None => "".to_string(),
+            }
+        };
+        Ok(Outcome::Continue(output))
+    }
+
+    fn query(&mut self, q: &str) -> Result<String>
+    {
+        if let Some(aggregate) = Aggregate::parse(q)
+        {
+            if aggregate.op == AggregateOp::Distinct
+            {
+                let values = aggregate.values(self.runtime.world_tree())?;
+                return Ok(values.iter().map(|v| v.to_string()).collect::<Vec<String>>().join(", "));
+            }
+            return Ok(match aggregate.evaluate(self.runtime.world_tree())?
+            {
+                Some(val) => RellSym::new(val).to_string(),
+                None => "none".to_string(),
+            });
+        }
+
+        let matches = query::query(q, self.runtime.world_tree())?;
+        if matches.is_empty()
+        {
+            return Ok("no".to_string());
+        }
+
+        let lines: Vec<String> = matches.iter().map(|m| {
+            if m.bindings.is_empty()
+            {
+                "yes".to_string()
+            }
+            else
+            {
+                m.bindings.iter().map(|(var, val)| format!("{} = {}", var, val)).collect::<Vec<String>>().join(", ")
+            }
+        }).collect();
+        Ok(lines.join("\n"))
+    }
+
+    fn command(&mut self, command: &str, arg: &str) -> Result<String>
+    {
+        let output = match command
+        {
+            "help" | "h" => HELP.to_string(),
+            "show" => format!("{}", self.runtime.world_tree()).trim_end().to_string(),
+            "rules" => self.runtime.rule_names().join("\n"),
+            "functions" => self.runtime.function_names().join("\n"),
+            "step" => if self.runtime.step()? { "changed".to_string() } else { "unchanged".to_string() },
+            "run" => { self.runtime.update()?; "ok".to_string() },
+            "remove" => format!("removed {} nodes", self.runtime.world_tree_mut().remove_statement(arg)?.len()),
+            "call" =>
+            {
+                let (name, statement) = arg.split_once(char::is_whitespace).unwrap_or((arg, ""));
+                self.runtime.call_function(name, statement.trim())?;
+                "ok".to_string()
+            },
+            "load" => { self.runtime.load_file(arg)?; "ok".to_string() },
+            "save" => { self.runtime.world_tree().save(File::create(arg)?)?; "ok".to_string() },
+            _ => format!("Unknown command :{}, try :help", command),
+        };
+        Ok(output)
+    }
+}
+
+fn main() -> io::Result<()>
+{
+    let mut repl = Repl::new();
+    for path in std::env::args().skip(1)
+    {
+        if let Err(e) = repl.runtime.load_file(&path)
+        {
+            eprintln!("{}: {}", path, e);
+        }
+    }
+
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    loop
+    {
+        write!(stdout, "rell> ")?;
+        stdout.flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0
+        {
+            break; // EOF
+        }
+
+        match repl.eval(&line)
+        {
+            Ok(Outcome::Quit) => break,
+            Ok(Outcome::Continue(output)) => if !output.is_empty() { writeln!(stdout, "{}", output)? },
+            Err(e) => writeln!(stdout, "error: {}", e)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    fn eval(repl: &mut Repl, line: &str) -> Result<String>
+    {
+        match repl.eval(line)?
+        {
+            Outcome::Continue(output) => Ok(output),
+            Outcome::Quit => Ok(":quit".to_string()),
+        }
+    }
+
+    #[test]
+    fn repl() -> Result<()>
+    {
+        let mut repl = Repl::new();
+        assert_eq!(eval(&mut repl, "city.in.state")?, "ok");
+        assert_eq!(eval(&mut repl, "state.in.country")?, "ok");
+        assert_eq!(eval(&mut repl, "# comment")?, "");
+        assert_eq!(eval(&mut repl, "rule transitive: X.in.Y, Y.in.Z => X.in.Z")?, "ok");
+        assert_eq!(eval(&mut repl, ":rules")?, "transitive");
+
+        assert_eq!(eval(&mut repl, "?city.in.country")?, "no");
+        assert_eq!(eval(&mut repl, ":run")?, "ok");
+        assert_eq!(eval(&mut repl, "?city.in.country")?, "yes");
+        assert_eq!(eval(&mut repl, "?X.in.state")?, "X = city");
+        assert_eq!(eval(&mut repl, "?count(X : X.in.country)")?, "2");
+        assert_eq!(eval(&mut repl, "?max(X : X.in.nowhere)")?, "none");
+        assert_eq!(eval(&mut repl, "?distinct(Y : X.in.Y)")?, "country, state");
+
+        assert_eq!(eval(&mut repl, ":remove city.in")?, "removed 3 nodes");
+        assert_eq!(eval(&mut repl, ":show")?, "ROOT\n-city\n-state\n--in\n---country");
+        assert_eq!(eval(&mut repl, ":quit")?, ":quit");
+
+        assert!(eval(&mut repl, "city.in!country").is_ok());
+        assert!(eval(&mut repl, "city.in.state").is_err());
+        assert!(eval(&mut repl, ":call nope func!nope").is_err());
+
+        Ok(())
+    }
+}
@@ -1,16 +1,28 @@
 use std::collections::BTreeMap;
 
 use crate::rellcore::*;
-use crate::rellcore::errors::{ Result, Error };
+use crate::rellcore::errors::{ Result, Error, Span };
+use crate::logic::implications::BindableImplication;
+use crate::expression::Expression;
+use crate::trace::{ self, TraceEvent };
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ParseToken
 {
-    Symbol(SID, usize, usize),
+    Symbol(SID, Span),
     Exclusive,
     NonExclusive,
     EOL
 }
+// A single line of a .rell file
+#[derive(Debug, Clone, PartialEq)]
+pub enum Definition
+{
+    Fact(String),
+    Rule { name: Option<String>, priors: Vec<String>, posteriors: Vec<String> },
+    Function { name: String, signature: String, priors: Vec<String>, posteriors: Vec<String> },
+}
+
 pub struct RellParser;
 impl RellParser
 {
@@ -30,8 +42,8 @@ impl RellParser
             let token = token_it.next().unwrap();
             match token
             {
-                ParseToken::Symbol(sid, from, to) => {
-                    let sym = statement.get(*from..*to).unwrap();
+                ParseToken::Symbol(sid, span) => {
+                    let sym = statement.get(span.start..span.end).unwrap();
                     let sid = *sid;
 
                     let edge = match token_it.next().unwrap()
@@ -39,41 +51,58 @@ impl RellParser
                         ParseToken::Exclusive    => RellE::Exclusive(sid, Self::NID_INVALID),
                         ParseToken::NonExclusive => RellE::NonExclusive(BTreeMap::new()),
                         ParseToken::EOL => RellE::Empty,
-                        err_tok => return Err(Error::CustomError(format!("Found Token {:?} after {:?}", err_tok, token))),
+                        err_tok => return Err(Error::UnexpectedToken { token: format!("{:?}", err_tok),
+                                                                       span: Span::new(statement, span.end, span.end),
+                                                                       statement: statement.to_string() }),
                     };
 
                     let n = RellN { edge, sym: sid, parent: RellN::NID_INVALID };
-                    println!("{:?}", n);
                     nodes.push(n);
 
                     let val = match sym.chars().next().unwrap()
                     {
-                        '0'..='9' => {
-                            match sym.parse::<f32>()
+                        Self::QUOTE => {
+                            RellSymValue::Literal(Self::unquote(sym).unwrap())
+                        },
+                        '0'..='9' | '-' | Expression::OPEN if Self::is_numeric(sym) => {
+                            match Self::parse_numeric(sym).unwrap()
                             {
-                                Ok(num) => RellSymValue::Numeric(num),
-                                Err(e)  => return Err(Error::CustomError(format!("{}", e))),
+                                Ok(RellSymValue::Float(n)) if !n.is_finite() =>
+                                    return Err(Error::NumericOverflow { span: *span, statement: statement.to_string() }),
+                                Ok(num) => num,
+                                Err(e)  => return Err(Error::NumericParse { source: e,
+                                                                            span: *span,
+                                                                            statement: statement.to_string() }),
                             }
                         },
                         'A'..='Z' => {
                             RellSymValue::Identifier(sym.to_string())
                         },
+                        '_' if sym == Self::WILDCARD => {
+                            RellSymValue::Identifier(sym.to_string())
+                        },
 
                         // No need to check for invalid characters, that was done by the tokenizer
                         _ => { RellSymValue::Literal(sym.to_string()) }
                     };
-                    println!("{:?}",val);
                     syms.push(RellSym::new(val))
                 },
                 err_tok => {
-                    return Err(Error::CustomError(format!("Upstream Error: TOKENIZER - received unreasonable token sequence TOKEN: {:?} when expecting a SYMBOL", err_tok)));
+                    // Upstream Error: TOKENIZER - received unreasonable token sequence when expecting a SYMBOL
+                    return Err(Error::UnexpectedToken { token: format!("{:?}", err_tok),
+                                                        span: Span::new(statement, statement.len(), statement.len()),
+                                                        statement: statement.to_string() });
                 }
             }
         }
 
+        trace::emit(|| TraceEvent::Parsed { statement: statement.to_string(), symbols: syms.clone() });
         Ok((nodes, syms))
     }
 
+    // Matches anything, like a variable that is never bound. Every `_` is a different one
+    pub const WILDCARD: &'static str = "_";
+
     pub fn tokenize<S, SF>(statement: S, sidfactory: &SF) -> Result<Vec<ParseToken>>
         where S: AsRef<str>, SF: SIDGenerator
     {
@@ -85,11 +114,17 @@ impl RellParser
         {
             let i_eos = Self::find_next_eos(statement, scan)?;
             let sym = statement.get(scan..i_eos).unwrap();
-            let sid = sidfactory.get_sid(&sym);
+            let sid = match (Self::unquote(sym), Self::parse_numeric(sym))
+            {
+                (Some(literal), _)   => sidfactory.get_sid_no_binding(RellSym::new(RellSymValue::Literal(literal)).sid_key()),
+                (None, Some(Ok(num))) => sidfactory.get_sid(RellSym::new(num).sid_key()), // 50.0 is 50
+                // Variables go through get_sid, so they resolve to their bound value
+                _ if sym.starts_with(|c: char| c.is_ascii_uppercase()) || sym == Self::WILDCARD => sidfactory.get_sid(sym),
+                // Same key as the symbol table uses, `a b` is stored as "a b"
+                _                     => sidfactory.get_sid_no_binding(RellSym::new(RellSymValue::Literal(sym.to_string())).sid_key()),
+            };
 
-            let pt = ParseToken::Symbol(sid, scan, i_eos);
-            println!("TOKEN: {:?}", pt);
-            qt.push(pt);
+            qt.push(ParseToken::Symbol(sid, Span::new(statement, scan, i_eos)));
 
             qt.push(
                 match statement.get(i_eos..i_eos+1)
@@ -97,42 +132,351 @@ impl RellParser
                     Some(".") => ParseToken::NonExclusive,
                     Some("!") => ParseToken::Exclusive,
                     None      => ParseToken::EOL,
-                    Some(ch) => return Err(Error::CustomError(format!("Upstream Error: SCANNER - marked {} as EOS at {}", ch, i_eos))),
+                    // Upstream Error: SCANNER - marked something else as EOS
+                    Some(ch) => return Err(Error::UnexpectedToken { token: ch.to_string(),
+                                                                    span: Span::new(statement, i_eos, i_eos + 1),
+                                                                    statement: statement.to_string() }),
                 }
             );
             scan = i_eos+1;
         }
 
+        trace::emit(|| TraceEvent::Tokenized { statement: statement.to_string(), tokens: qt.clone() });
         Ok(qt)
     }
 
-    const INVALID_CHARS: &'static str = "%$@#,][";
+    const INVALID_CHARS: &'static str = "%$@#,][<>=";
+    // start and the returned EOS are byte offsets into the statement
     pub fn find_next_eos<S>(statement: S, start: usize) -> Result<usize>
         where S: AsRef<str>
     {
         let statement = statement.as_ref();
+        let invalid_char = |ch: char, str_i: usize| Error::InvalidChar { ch,
+                                                                         span: Span::new(statement, str_i, str_i + ch.len_utf8()),
+                                                                         statement: statement.to_string() };
+
+        let rest = match statement.get(start..)
+        {
+            Some(rest) => rest,
+            None => return Err(Error::UnexpectedToken { token: "non char boundary".to_string(),
+                                                        span: Span::new(statement, start, start),
+                                                        statement: statement.to_string() }),
+        };
+
+        // Quoted literals and (2.5) numbers can have edge chars inside, the symbol ends after them
+        let closed_at = if rest.starts_with(Self::QUOTE)
+        {
+            Some(Self::find_closing_quote(statement, start)? + 1)
+        }
+        else if rest.starts_with(Expression::OPEN)
+        {
+            match rest.find(Expression::CLOSE)
+            {
+                Some(i) => Some(start + i + 1),
+                None => return Err(invalid_char(Expression::OPEN, start)),
+            }
+        }
+        else
+        {
+            None
+        };
+
+        if let Some(after) = closed_at
+        {
+            return match statement[after..].chars().next()
+            {
+                None => Ok(statement.len()),
+                Some(ch @ '.') | Some(ch @ '!') if after + ch.len_utf8() == statement.len() => Err(invalid_char(ch, after)),
+                Some('.') | Some('!') => Ok(after),
+                Some(ch) => Err(invalid_char(ch, after)),
+            };
+        }
 
-        for (i, curr_c) in statement.chars().skip(start).enumerate()
+        for (i, curr_c) in rest.char_indices()
         {
             let str_i = start + i;
-            if let '.' | '!' = curr_c
+            if curr_c == '!' || curr_c == '.'
             {
-                if  i == 0 || str_i == statement.len() - 1 // ! or . at the beginning or end is not allowed
+                if  i == 0 || str_i + curr_c.len_utf8() == statement.len() // ! or . at the beginning or end is not allowed
                 {
-                    return Err(Error::InvalidChar(curr_c, str_i));
+                    return Err(invalid_char(curr_c, str_i));
                 }
                 return Ok(str_i);
             }
 
             if  RellParser::INVALID_CHARS.contains(curr_c)
             {
-                return Err(Error::InvalidChar(curr_c, str_i));
+                return Err(invalid_char(curr_c, str_i));
             }
         }
         Ok(statement.len())
     }
 }
 
+// Numbers - 50, -5, 1e9, (0.25). A `.` between digits is an edge in a statement, so
+// anything with a decimal point goes in parentheses: grid.3.5 is a path, grid.(3.5) a number
+impl RellParser
+{
+    fn is_numeric(sym: &str) -> bool
+    {
+        sym.starts_with(Expression::OPEN) || sym.strip_prefix('-').unwrap_or(sym).starts_with(|c: char| c.is_ascii_digit())
+    }
+
+    // None if sym isn't a number at all, canonical value otherwise (integral floats become integers)
+    pub(crate) fn parse_numeric(sym: &str) -> Option<std::result::Result<RellSymValue, std::num::ParseFloatError>>
+    {
+        if !Self::is_numeric(sym)
+        {
+            return None;
+        }
+
+        let sym = sym.strip_prefix(Expression::OPEN).and_then(|s| s.strip_suffix(Expression::CLOSE)).unwrap_or(sym);
+        Some(match sym.parse::<i64>()
+        {
+            Ok(i)  => Ok(RellSymValue::Integer(i)),
+            Err(_) => sym.parse::<f64>().map(RellSymValue::from_f64),
+        })
+    }
+}
+
+// Quoted Literals - "Sir Robin of Camelot", "3.2.1", "say \"hi\""
+impl RellParser
+{
+    pub const QUOTE: char = '"';
+    const ESCAPE: char = '\\';
+    const ESCAPABLE_CHARS: &'static str = "\"\\nt";
+
+    // Byte offset of the quote closing the literal that opens at start
+    fn find_closing_quote(statement: &str, start: usize) -> Result<usize>
+    {
+        let mut escaped = false;
+        for (i, curr_c) in statement[start..].char_indices().skip(1)
+        {
+            let str_i = start + i;
+            if escaped
+            {
+                if !Self::ESCAPABLE_CHARS.contains(curr_c)
+                {
+                    return Err(Error::InvalidChar { ch: curr_c, span: Span::new(statement, str_i, str_i + curr_c.len_utf8()), statement: statement.to_string() });
+                }
+                escaped = false;
+            }
+            else if curr_c == Self::ESCAPE
+            {
+                escaped = true;
+            }
+            else if curr_c == Self::QUOTE
+            {
+                return Ok(str_i);
+            }
+        }
+        Err(Error::UnterminatedLiteral { span: Span::new(statement, start, statement.len()), statement: statement.to_string() })
+    }
+
+    // Contents of a quoted symbol (already validated by the scanner), None if it isn't quoted
+    pub fn unquote(sym: &str) -> Option<String>
+    {
+        if sym.len() < 2 || !sym.starts_with(Self::QUOTE) || !sym.ends_with(Self::QUOTE)
+        {
+            return None;
+        }
+
+        let mut literal = String::new();
+        let mut chars = sym[1..sym.len() - 1].chars();
+        while let Some(c) = chars.next()
+        {
+            literal.push(match c
+            {
+                Self::ESCAPE => match chars.next()?
+                {
+                    'n' => '\n',
+                    't' => '\t',
+                    escaped => escaped,
+                },
+                c => c,
+            });
+        }
+        Some(literal)
+    }
+
+    // Quotes (and escapes) literals that wouldn't parse back as the same literal otherwise
+    pub fn quote_if_needed(literal: &str) -> String
+    {
+        let needs_quotes = match literal.chars().next()
+        {
+            None => true,
+            Some('A'..='Z') | Some('0'..='9') | Some(Expression::OPEN) => true,
+            Some(_) if Self::is_numeric(literal) || literal == Self::WILDCARD => true,
+            Some(_) => literal.chars().any(|c| {
+                c.is_whitespace() || c.is_control() || ".!".contains(c) || c == Self::QUOTE || c == Self::ESCAPE || Self::INVALID_CHARS.contains(c)
+            }),
+        };
+
+        if !needs_quotes
+        {
+            return literal.to_string();
+        }
+
+        let mut quoted = Self::QUOTE.to_string();
+        for c in literal.chars()
+        {
+            match c
+            {
+                '\n' => quoted.push_str("\\n"),
+                '\t' => quoted.push_str("\\t"),
+                Self::QUOTE | Self::ESCAPE => { quoted.push(Self::ESCAPE); quoted.push(c); },
+                c => quoted.push(c),
+            }
+        }
+        quoted.push(Self::QUOTE);
+        quoted
+    }
+
+    // Splits on separator, ignoring anything inside quoted literals or parentheses
+    pub(crate) fn split_top_level<'a>(s: &'a str, separator: &str) -> Vec<&'a str>
+    {
+        let mut parts = vec![];
+        let mut start = 0;
+        let mut depth = 0;
+        let mut quoted = false;
+        let mut escaped = false;
+        for (i, c) in s.char_indices()
+        {
+            if escaped
+            {
+                escaped = false;
+                continue;
+            }
+
+            match c
+            {
+                Self::ESCAPE if quoted => escaped = true,
+                Self::QUOTE => quoted = !quoted,
+                '(' if !quoted => depth += 1,
+                ')' if !quoted && depth > 0 => depth -= 1,
+                _ if !quoted && depth == 0 && i >= start && s[i..].starts_with(separator) =>
+                {
+                    parts.push(&s[start..i]);
+                    start = i + separator.len();
+                },
+                _ => {}
+            }
+        }
+        parts.push(&s[start..]);
+        parts
+    }
+}
+
+// Rules & Definitions
+//
+//  # comment
+//  goat.in!left                                         <- Fact
+//  X.in.Y, Y.in.Z => X.in.Z                             <- Anonymous rule
+//  rule transitive: X.in.Y, Y.in.Z => X.in.Z            <- Named rule
+//  X.drops!O => -X.holds!O, delete X.drops               <- Rule retracting facts
+//  fn move: func!move.X.to.Y, X.in!Z => X.in!Y          <- Function, first prior is the call signature
+impl RellParser
+{
+    pub const COMMENT: char = '#';
+    pub const RULE_KEYWORD: &'static str = "rule ";
+    pub const FUNCTION_KEYWORD: &'static str = "fn ";
+    pub const IMPLICATION: &'static str = "=>";
+    pub const STATEMENT_SEPARATOR: char = ',';
+
+    pub fn parse_rule<S>(rule: S) -> Result<BindableImplication>
+        where S: AsRef<str>
+    {
+        let (priors, posteriors) = Self::split_rule(rule.as_ref())?;
+        BindableImplication::from_statements(priors, posteriors)
+    }
+
+    pub fn parse_definition<S>(line: S) -> Result<Option<Definition>>
+        where S: AsRef<str>
+    {
+        let line = line.as_ref().trim();
+        if line.is_empty() || line.starts_with(Self::COMMENT)
+        {
+            return Ok(None);
+        }
+
+        let definition = if let Some(rest) = line.strip_prefix(Self::RULE_KEYWORD)
+        {
+            let (name, rule) = Self::split_name(rest, line)?;
+            let (priors, posteriors) = Self::split_rule(rule)?;
+            Definition::Rule { name: Some(name), priors, posteriors }
+        }
+        else if let Some(rest) = line.strip_prefix(Self::FUNCTION_KEYWORD)
+        {
+            let (name, rule) = Self::split_name(rest, line)?;
+            let (mut priors, posteriors) = Self::split_rule(rule)?;
+            if priors.is_empty()
+            {
+                return Err(Error::MalformedRule { rule: line.to_string(), reason: "function is missing its call signature".to_string() });
+            }
+            let signature = priors.remove(0);
+            Definition::Function { name, signature, priors, posteriors }
+        }
+        else if Self::split_top_level(line, Self::IMPLICATION).len() > 1
+        {
+            let (priors, posteriors) = Self::split_rule(line)?;
+            Definition::Rule { name: None, priors, posteriors }
+        }
+        else
+        {
+            Definition::Fact(line.to_string())
+        };
+
+        Ok(Some(definition))
+    }
+
+    // `A.b, C.d` -> [`A.b`, `C.d`]
+    pub fn split_statements(statements: &str) -> Vec<&str>
+    {
+        Self::split_top_level(statements, &Self::STATEMENT_SEPARATOR.to_string()).into_iter().map(|s| s.trim()).collect()
+    }
+
+    fn split_name<'a>(rest: &'a str, line: &str) -> Result<(String, &'a str)>
+    {
+        match rest.split_once(':')
+        {
+            Some((name, rule)) if !name.trim().is_empty() && !name.trim().contains(char::is_whitespace) =>
+                Ok((name.trim().to_string(), rule)),
+            _ => Err(Error::MalformedRule { rule: line.to_string(), reason: "expected `<name>: ...`".to_string() }),
+        }
+    }
+
+    fn split_rule(rule: &str) -> Result<(Vec<String>, Vec<String>)>
+    {
+        let malformed = |reason: &str| Error::MalformedRule { rule: rule.trim().to_string(), reason: reason.to_string() };
+
+        let sides = Self::split_top_level(rule, Self::IMPLICATION);
+        if sides.len() != 2
+        {
+            return Err(malformed("expected exactly one `=>`"));
+        }
+
+        let split_statements = |side: &str| -> Result<Vec<String>> {
+            if side.trim().is_empty()
+            {
+                return Ok(vec![]);
+            }
+
+            Self::split_statements(side).into_iter().map(|s| {
+                if s.is_empty() { Err(malformed("empty statement")) } else { Ok(s.to_string()) }
+            }).collect()
+        };
+
+        let priors = split_statements(sides[0])?;
+        let posteriors = split_statements(sides[1])?;
+        if posteriors.is_empty()
+        {
+            return Err(malformed("rule has no posteriors"));
+        }
+
+        Ok((priors, posteriors))
+    }
+}
+
 #[cfg(test)]
 mod test
 {
@@ -150,17 +494,17 @@ mod test
         // Error cases
         {
             let err = RellParser::find_next_eos("brown.is,happy", 6);
-            assert!(if let Err(Error::InvalidChar(',', 8)) = err { true } else { false }, "Result is: {:?}", err);
+            assert!(matches!(err, Err(Error::InvalidChar { ch: ',', span: Span { start: 8, .. }, .. })), "Result is: {:?}", err);
         }
 
         {
             let err = RellParser::find_next_eos("brown@is.happy", 0);
-            assert!(if let Err(Error::InvalidChar('@', 5)) = err { true } else { false }, "Result is: {:?}", err);
+            assert!(matches!(err, Err(Error::InvalidChar { ch: '@', span: Span { start: 5, .. }, .. })), "Result is: {:?}", err);
         }
 
         {
             let err = RellParser::find_next_eos("brown.is.", 6);
-            assert!(if let Err(Error::InvalidChar('.', 8)) = err { true } else { false }, "Result is: {:?}", err);
+            assert!(matches!(err, Err(Error::InvalidChar { ch: '.', span: Span { start: 8, .. }, .. })), "Result is: {:?}", err);
         }
     }
 
@@ -168,18 +512,133 @@ mod test
     fn tokenization()
     {
         let w = SymbolsTable::new();
-        let expected = vec![ParseToken::Symbol(w.get_sid("brown"), 0, 5), ParseToken::Exclusive,
-                            ParseToken::Symbol(w.get_sid("is"),    6, 8), ParseToken::EOL];
+        let expected = vec![ParseToken::Symbol(w.get_sid("brown"), Span::new("brown!is", 0, 5)), ParseToken::Exclusive,
+                            ParseToken::Symbol(w.get_sid("is"),    Span::new("brown!is", 6, 8)), ParseToken::EOL];
 
         assert_eq!(expected, RellParser::tokenize("brown!is", &w).unwrap());
     }
 
+    #[test]
+    fn unicode()
+    {
+        let w = SymbolsTable::new();
+
+        assert_eq!(RellParser::find_next_eos("café.in!paris", 0).unwrap(), 5);
+        assert_eq!(RellParser::find_next_eos("café.in!paris", 6).unwrap(), 8);
+
+        let tokens = RellParser::tokenize("café.in!paris", &w).unwrap();
+        assert_eq!(tokens[0], ParseToken::Symbol(w.get_sid("café"), Span { start: 0, end: 5, char_start: 0, char_end: 4 }));
+        assert_eq!(tokens[2], ParseToken::Symbol(w.get_sid("in"),   Span { start: 6, end: 8, char_start: 5, char_end: 7 }));
+        assert_eq!(tokens[4], ParseToken::Symbol(w.get_sid("paris"), Span { start: 9, end: 14, char_start: 8, char_end: 13 }));
+
+        let (_, syms) = RellParser::parse_simple_statement("山田.住所!東京", &w).unwrap();
+        assert_eq!(syms.iter().map(|s| s.to_string()).collect::<Vec<String>>(), vec!["山田", "住所", "東京"]);
+
+        let err = RellParser::parse_simple_statement("東京,x", &w);
+        assert!(matches!(err, Err(Error::InvalidChar { ch: ',', span: Span { start: 6, end: 7, char_start: 2, char_end: 3 }, .. })), "Result is: {:?}", err);
+        assert!(RellParser::parse_simple_statement("東京.", &w).is_err());
+        assert!(RellParser::find_next_eos("東京", 1).is_err());
+    }
+
+    #[test]
+    fn numbers() -> Result<()>
+    {
+        let w = SymbolsTable::new();
+        let num = |s: &str| RellParser::parse_simple_statement(format!("x!{}", s), &w).map(|(_, syms)| syms[1].get_val().clone());
+
+        assert_eq!(num("50")?, RellSymValue::Integer(50));
+        assert!(matches!(num("(50.0)")?, RellSymValue::Integer(50)));
+        assert!(matches!(num("-5")?, RellSymValue::Integer(-5)));
+        assert!(matches!(num("(0.25)")?, RellSymValue::Float(n) if n == 0.25));
+        assert!(matches!(num("(-2.5)")?, RellSymValue::Float(n) if n == -2.5));
+        assert!(matches!(num("(two)"), Err(Error::NumericParse { .. })));
+        assert!(matches!(num("(2.5"), Err(Error::InvalidChar { ch: '(', .. })));
+        assert!(matches!(num("(2.5)x"), Err(Error::InvalidChar { ch: 'x', .. })));
+        assert!(matches!(num("1e3")?, RellSymValue::Integer(1000)));
+        assert!(matches!(num("-")?, RellSymValue::Literal(_)));
+        assert!(matches!(num("-five")?, RellSymValue::Literal(_)));
+        assert!(matches!(num("1e999"), Err(Error::NumericOverflow { .. })));
+
+        // Same SID, so the same fact
+        let (a, _) = RellParser::parse_simple_statement("hp!50", &w)?;
+        let (b, _) = RellParser::parse_simple_statement("hp!(50.0)", &w)?;
+        assert_eq!(a, b);
+
+        // Outside parentheses a . is always an edge
+        let (nodes, syms) = RellParser::parse_simple_statement("v.1.5.(2.5).x", &w)?;
+        assert_eq!(nodes.len(), 5);
+        assert!(matches!(syms[1].get_val(), RellSymValue::Integer(1)));
+        assert!(matches!(syms[2].get_val(), RellSymValue::Integer(5)));
+        assert!(matches!(syms[3].get_val(), RellSymValue::Float(n) if *n == 2.5));
+        assert_eq!(syms[3].to_string(), "(2.5)");
+        assert_eq!(RellParser::quote_if_needed("(2.5)"), "\"(2.5)\"");
+
+        assert!(RellSymValue::Integer(2) < RellSymValue::Float(2.5));
+        assert!(RellSymValue::Integer(-3) < RellSymValue::Integer(2));
+        assert_eq!(RellSymValue::Integer(2).partial_cmp(&RellSymValue::Literal("2".to_string())), None);
+        assert_eq!(RellParser::quote_if_needed("-5"), "\"-5\"");
+
+        let (_, syms) = RellParser::parse_simple_statement("_.in!_boat", &w)?;
+        assert_eq!(syms[0].get_val(), &RellSymValue::Identifier("_".to_string()));
+        assert_eq!(syms[2].get_val(), &RellSymValue::Literal("_boat".to_string()));
+        assert_eq!(RellParser::quote_if_needed("_"), "\"_\"");
+
+        Ok(())
+    }
+
+    #[test]
+    fn quoted_literals() -> Result<()>
+    {
+        let w = SymbolsTable::new();
+        let literal = |s: &str| RellSym::new(RellSymValue::Literal(s.to_string()));
+
+        let (_, syms) = RellParser::parse_simple_statement("npc.name!\"Sir Robin of Camelot\"", &w)?;
+        assert_eq!(syms, vec![literal("npc"), literal("name"), literal("Sir Robin of Camelot")]);
+
+        let (nodes, syms) = RellParser::parse_simple_statement("app.\"3.2.1\".\"a!b, [c]\"", &w)?;
+        assert_eq!(syms, vec![literal("app"), literal("3.2.1"), literal("a!b, [c]")]);
+        assert_eq!(nodes[1].sym, w.get_sid("\"3.2.1\""));
+        assert_eq!(nodes.len(), 3);
+
+        let (_, syms) = RellParser::parse_simple_statement("say!\"\\\"hi\\\"\\n\\\\\".\"\"", &w)?;
+        assert_eq!(syms, vec![literal("say"), literal("\"hi\"\n\\"), literal("")]);
+
+        // Quoted and bare versions of the same literal are the same symbol
+        let (plain, _) = RellParser::parse_simple_statement("a.b", &w)?;
+        let (quoted, _) = RellParser::parse_simple_statement("\"a\".\"b\"", &w)?;
+        assert_eq!(plain, quoted);
+        let (plain, _) = RellParser::parse_simple_statement("a b.c", &w)?;
+        let (quoted, _) = RellParser::parse_simple_statement("\"a b\".c", &w)?;
+        assert_eq!(plain, quoted);
+
+        let err = RellParser::parse_simple_statement("npc.name!\"Sir Robin", &w);
+        assert!(matches!(err, Err(Error::UnterminatedLiteral { span: Span { start: 9, .. }, .. })), "Result is: {:?}", err);
+        let err = RellParser::parse_simple_statement("npc.\"bad\\q\"", &w);
+        assert!(matches!(err, Err(Error::InvalidChar { ch: 'q', .. })), "Result is: {:?}", err);
+        let err = RellParser::parse_simple_statement("npc.\"a\"b", &w);
+        assert!(matches!(err, Err(Error::InvalidChar { ch: 'b', .. })), "Result is: {:?}", err);
+        assert!(RellParser::parse_simple_statement("npc.\"a\".", &w).is_err());
+
+        for s in &["robin", "Sir Robin", "3.2.1", "", "a\"b\\c\nd", "X", "東京", "a,b"]
+        {
+            let quoted = RellParser::quote_if_needed(s);
+            let (_, syms) = RellParser::parse_simple_statement(format!("x.{}", quoted), &w)?;
+            assert_eq!(syms[1], literal(s), "{} did not round trip through {}", s, quoted);
+        }
+        assert_eq!(RellParser::quote_if_needed("robin"), "robin");
+
+        assert_eq!(RellParser::split_statements("a.\"b, c\", d.e"), vec!["a.\"b, c\"", "d.e"]);
+        assert_eq!(RellParser::parse_definition("x.is!\"=>\"")?, Some(Definition::Fact("x.is!\"=>\"".to_string())));
+
+        Ok(())
+    }
+
     #[test]
     fn parse() -> Result<()>
     {
         let w = SymbolsTable::new();
         let err = RellParser::parse_simple_statement("brown..nope", &w);
-        assert!(if let Err(Error::InvalidChar('.', 6)) = err { true } else { false }, "Result is: {:?}", err);
+        assert!(matches!(err, Err(Error::InvalidChar { ch: '.', span: Span { start: 6, .. }, .. })), "Result is: {:?}", err);
 
         let (_, syms) = RellParser::parse_simple_statement("brown.lastname.perez", &w)?;
 
@@ -193,27 +652,63 @@ mod test
         let expected2 = vec![
             RellSym::new( RellSymValue::Literal("brown".to_string()) ),
             RellSym::new( RellSymValue::Literal("height".to_string())),
-            RellSym::new( RellSymValue::Numeric(50.0)    )];
+            RellSym::new( RellSymValue::Integer(50)    )];
         assert_eq!(syms2, expected2, "{:?}", syms2);
 
         let (_, syms3) = RellParser::parse_simple_statement("brown.Height!50", &w)?;
         let expected3 = vec![
             RellSym::new( RellSymValue::Literal("brown".to_string())    ),
             RellSym::new( RellSymValue::Identifier("Height".to_string()) ),
-            RellSym::new( RellSymValue::Numeric(50.0)    )];
+            RellSym::new( RellSymValue::Integer(50)    )];
         assert_eq!(syms3, expected3, "{:?}", syms3);
 
 
         let result = RellParser::parse_simple_statement("brown.height!5m", &w);
-        if let Err(Error::CustomError(_)) = result
-        {
-            Ok(())
-        }
-        else
+        assert!(matches!(result, Err(Error::NumericParse { span: Span { start: 13, end: 15, .. }, .. })), "Unexpected Result {:?}", result);
+        assert_eq!(format!("{}", result.unwrap_err()), "Invalid number `5m` (invalid float literal) in `brown.height!5m`");
+
+        let err = RellParser::parse_simple_statement("brown,nope", &w).unwrap_err();
+        assert_eq!(format!("{}", err), "Invalid char ',' at 5 in `brown,nope`");
+        Ok(())
+    }
+
+    #[test]
+    fn rules() -> Result<()>
+    {
+        assert!(RellParser::parse_rule("X.in.Y, Y.in.Z => X.in.Z").is_ok());
+        assert!(RellParser::parse_rule("X.gold!G, X.earned!E => X.gold!(G + E)").is_ok());
+        assert!(RellParser::parse_rule("X.hp!H => X.hp.(H - 1)").is_err(), "Computed symbols must be exclusive");
+        assert!(RellParser::parse_rule("X.drops!O => -X.holds!O, delete X.drops").is_ok());
+
+        for bad in &["X.in.Y, Y.in.Z", "X.in.Y => ", "X.in.Y,, Y.in.Z => X.in.Z", "A => B => C"]
         {
-            Err(Error::CustomError(format!("Unexpected Result {:?}", result)))
+            let err = RellParser::parse_rule(bad);
+            assert!(matches!(err, Err(Error::MalformedRule { .. })), "Result for {} is: {:?}", bad, err);
         }
-    }
-}
 
+        assert_eq!(RellParser::parse_definition("   ")?, None);
+        assert_eq!(RellParser::parse_definition("# A comment")?, None);
+        assert_eq!(RellParser::parse_definition(" goat.in!left ")?, Some(Definition::Fact("goat.in!left".to_string())));
+
+        assert_eq!(RellParser::parse_definition("X.in.Y, Y.in.Z => X.in.Z")?,
+                   Some(Definition::Rule { name: None,
+                                           priors: vec!["X.in.Y".to_string(), "Y.in.Z".to_string()],
+                                           posteriors: vec!["X.in.Z".to_string()] }));
 
+        assert_eq!(RellParser::parse_definition("rule transitive: X.in.Y, Y.in.Z => X.in.Z")?,
+                   Some(Definition::Rule { name: Some("transitive".to_string()),
+                                           priors: vec!["X.in.Y".to_string(), "Y.in.Z".to_string()],
+                                           posteriors: vec!["X.in.Z".to_string()] }));
+
+        assert_eq!(RellParser::parse_definition("fn move: func!move.X.to.Y, X.in!Z => X.in!Y")?,
+                   Some(Definition::Function { name: "move".to_string(),
+                                               signature: "func!move.X.to.Y".to_string(),
+                                               priors: vec!["X.in!Z".to_string()],
+                                               posteriors: vec!["X.in!Y".to_string()] }));
+
+        assert!(matches!(RellParser::parse_definition("rule : X.in.Y => X.is.in"), Err(Error::MalformedRule { .. })));
+        assert!(matches!(RellParser::parse_definition("fn noop: => X.is.in"), Err(Error::MalformedRule { .. })));
+
+        Ok(())
+    }
+}
@@ -1,9 +1,37 @@
+use std::collections::BTreeMap;
+
 use crate::RellTree;
 use crate::binding::*;
+use crate::parser::*;
+use crate::rellcore::*;
+use crate::rellcore::errors::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryMatch
+{
+    pub bindings: BTreeMap<String, RellSym>, // Variable Name -> Bound Value
+    pub nids: Vec<NID>,                      // Matched node for each statement, in query order
+}
 
+impl QueryMatch
+{
+    pub fn get<S>(&self, variable: S) -> Option<&RellSym> where S: AsRef<str>
+    {
+        self.bindings.get(variable.as_ref())
+    }
+
+    // Node matched by the first statement of the query, None if it only had negations/constraints
+    pub fn nid(&self) -> Option<NID>
+    {
+        self.nids.first().copied()
+    }
+}
+
+#[derive(Default)]
 pub struct QueryState
 {
     binding_state: BindingState,
+    statements: Vec<String>,
 }
 
 impl QueryState
@@ -13,6 +41,7 @@ impl QueryState
         Self
         {
             binding_state: BindingState::new(),
+            statements: vec![],
         }
     }
 
@@ -24,24 +53,203 @@ impl QueryState
 
         Self
         {
-            binding_state
+            binding_state,
+            statements: vec![query.as_ref().to_string()],
         }
 
     }
+
+    // Conjunctive query, e.g. `X.in.Y, Y.in.country` or `X.in.Y, not Y.in.country`
+    pub fn from_query<S>(query: S) -> Self where S: AsRef<str>
+    {
+        let mut q_state = Self::new();
+        for prior in RellParser::split_statements(query.as_ref()).into_iter().filter(|s| !s.is_empty())
+        {
+            q_state.binding_state.add_prior(prior);
+            if !prior.starts_with(BindingState::NEGATION_KEYWORD) && Constraint::parse(prior).is_none()
+            {
+                q_state.statements.push(prior.to_string());
+            }
+        }
+        q_state
+    }
+
+    pub fn run_on(&mut self, tree: &RellTree) -> Result<Vec<QueryMatch>>
+    {
+        let mut variables = BTreeMap::new();
+        for statement in &self.statements
+        {
+            let (_, syms) = RellParser::parse_simple_statement(statement, &tree.symbols)?;
+            for sym in syms
+            {
+                if let RellSymValue::Identifier(name) = sym.get_val()
+                {
+                    variables.insert(tree.symbols.get_sid(name), name.clone());
+                }
+            }
+        }
+
+        let matches = self.binding_state.generate_matches_on(tree)?;
+        Ok(matches.into_iter().map(|m| {
+            let bindings = m.vars.iter().filter_map(|(var_sid, val_sid)| {
+                Some((variables.get(var_sid)?.clone(), tree.symbols.get_sym(val_sid)?.clone()))
+            }).collect();
+            let nids = self.statements.iter().map(|s| m.nids[s]).collect();
+
+            QueryMatch { bindings, nids }
+        }).collect())
+    }
 }
 
 pub fn query_on<S>(query: S, tree: &RellTree) -> Vec<String> where S: AsRef<str>
 {
     let mut q_state = QueryState::from_statement(&query); 
-    q_state.binding_state.generate_compatible_on(tree);
-    q_state.binding_state.get_all_bound_paths_for(&query)
+    match q_state.binding_state.generate_compatible_on(tree)
+    {
+        Ok(_)  => q_state.binding_state.get_all_bound_paths_for(&query),
+        Err(_) => vec![]
+    }
+}
+
+pub fn query<S>(query: S, tree: &RellTree) -> Result<Vec<QueryMatch>> where S: AsRef<str>
+{
+    QueryState::from_query(query).run_on(tree)
+}
+
+// Aggregates - `count(X : X.in!boat)`, `sum(W : man.carries.X, X.weight!W)`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AggregateOp
+{
+    Count,
+    Sum,
+    Min,
+    Max,
+    Distinct,
+}
+impl AggregateOp
+{
+    const NAMES: [(&'static str, AggregateOp); 5] = [("count", AggregateOp::Count), ("sum", AggregateOp::Sum),
+                                                     ("min", AggregateOp::Min), ("max", AggregateOp::Max),
+                                                     ("distinct", AggregateOp::Distinct)];
+}
+
+// An operation over the value a variable takes in every match of a query. Variables
+// inside the query are local to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregate
+{
+    pub op: AggregateOp,
+    pub var: String,
+    pub query: String,
+}
+impl Aggregate
+{
+    pub fn new<S>(op: AggregateOp, var: S, query: S) -> Self where S: AsRef<str>
+    {
+        Self { op, var: var.as_ref().trim().to_string(), query: query.as_ref().trim().to_string() }
+    }
+
+    // None if it isn't written as `op(Var : query)`
+    pub fn parse<S>(aggregate: S) -> Option<Self> where S: AsRef<str>
+    {
+        let aggregate = aggregate.as_ref().trim();
+        let (name, inner) = aggregate.strip_suffix(')')?.split_once('(')?;
+        let op = AggregateOp::NAMES.iter().find(|(n, _)| *n == name.trim())?.1;
+        let (var, query) = inner.split_once(':')?;
+        if !var.trim().starts_with(|c: char| c.is_ascii_uppercase()) || query.trim().is_empty()
+        {
+            return None;
+        }
+        Some(Self::new(op, var, query))
+    }
+
+    // Value of the variable in every match, once per distinct value for Distinct
+    pub fn values(&self, tree: &RellTree) -> Result<Vec<RellSym>>
+    {
+        let mut values = vec![];
+        for m in query(&self.query, tree)?
+        {
+            let val = m.get(&self.var).ok_or_else(|| self.malformed(format!("`{}` is not bound by the query", self.var)))?;
+            if self.op != AggregateOp::Distinct || !values.contains(val)
+            {
+                values.push(val.clone());
+            }
+        }
+        Ok(values)
+    }
+
+    // Count and Distinct give the number of values, Min and Max are None if nothing matches
+    pub fn evaluate(&self, tree: &RellTree) -> Result<Option<RellSymValue>>
+    {
+        let values = self.values(tree)?;
+        let extreme = |wanted: std::cmp::Ordering| -> Result<Option<RellSymValue>> {
+            let mut best: Option<&RellSymValue> = None;
+            for val in values.iter().map(|v| v.get_val())
+            {
+                best = match best.map(|b| val.partial_cmp(b))
+                {
+                    None => Some(val),
+                    Some(Some(ord)) => if ord == wanted { Some(val) } else { best },
+                    Some(None) => return Err(self.malformed(format!("`{}` takes values that can't be compared", self.var))),
+                };
+            }
+            Ok(best.cloned())
+        };
+
+        match self.op
+        {
+            AggregateOp::Count | AggregateOp::Distinct => Ok(Some(RellSymValue::Integer(values.len() as i64))),
+            AggregateOp::Sum =>
+            {
+                let mut total = RellSymValue::Integer(0);
+                for val in &values
+                {
+                    total = match (total, val.get_val())
+                    {
+                        (RellSymValue::Integer(a), RellSymValue::Integer(b)) if a.checked_add(*b).is_some() => RellSymValue::Integer(a + b),
+                        (total, num) => match (total.as_f64(), num.as_f64())
+                        {
+                            (Some(a), Some(b)) => RellSymValue::from_f64(a + b),
+                            _ => return Err(self.malformed(format!("`{}` is not a number", val))),
+                        }
+                    };
+                }
+                Ok(Some(total))
+            },
+            AggregateOp::Min => extreme(std::cmp::Ordering::Less),
+            AggregateOp::Max => extreme(std::cmp::Ordering::Greater),
+        }
+    }
+
+    fn malformed(&self, reason: String) -> Error
+    {
+        Error::MalformedRule { rule: self.to_string(), reason }
+    }
+}
+
+impl std::fmt::Display for Aggregate
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        let name = AggregateOp::NAMES.iter().find(|(_, op)| *op == self.op).unwrap().0;
+        write!(f, "{}({} : {})", name, self.var, self.query)
+    }
+}
+
+// e.g. `aggregate("count(X : X.in.city)", &tree)`
+pub fn aggregate<S>(aggregate: S, tree: &RellTree) -> Result<Option<RellSymValue>> where S: AsRef<str>
+{
+    match Aggregate::parse(&aggregate)
+    {
+        Some(a) => a.evaluate(tree),
+        None => Err(Error::MalformedRule { rule: aggregate.as_ref().to_string(), reason: "expected `op(Var : query)`".to_string() }),
+    }
 }
 
 #[cfg(test)]
 mod test
 {
     use super::*;
-    use crate::errors::*;
 
     fn build_test_tree() -> Result<RellTree>
     {
@@ -64,9 +272,87 @@ mod test
 
         let q_result_2 = query_on("X.in.Y", &w);
         assert_eq!(q_result_2.len(), 3, "Incorrect number of results for bound query");
-        assert_eq!(q_result_2[0], "state.in.country", "Query didnt result expected value");
-        assert_eq!(q_result_2[1], "city.in.state", "Query didnt result expected value");
-        assert_eq!(q_result_2[2], "other_state.in.country", "Query didnt result expected value");
+        assert_eq!(q_result_2[0], "other_state.in.country", "Query didnt result expected value");
+        assert_eq!(q_result_2[1], "state.in.country", "Query didnt result expected value");
+        assert_eq!(q_result_2[2], "city.in.state", "Query didnt result expected value");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bindings() -> Result<()>
+    {
+        let w = build_test_tree()?;
+
+        let q_result = query("X.in.state", &w)?;
+        assert_eq!(q_result.len(), 1);
+        assert_eq!(q_result[0].get("X").unwrap().to_string(), "city");
+        assert_eq!(q_result[0].nid(), w.get_nid_at_path("city.in.state"));
+
+        let q_result_2 = query("X.in.Y, Y.in.country", &w)?;
+        assert_eq!(q_result_2.len(), 1, "Incorrect number of results for conjunctive query");
+        assert_eq!(q_result_2[0].bindings.len(), 2);
+        assert_eq!(q_result_2[0].get("X").unwrap().get_val(), &RellSymValue::Literal("city".to_string()));
+        assert_eq!(q_result_2[0].get("Y").unwrap().get_val(), &RellSymValue::Literal("state".to_string()));
+        assert_eq!(q_result_2[0].nids, vec![w.get_nid_at_path("city.in.state").unwrap(), w.get_nid_at_path("state.in.country").unwrap()]);
+
+        let q_result_3 = query("X.in.country, not X.in.state", &w)?;
+        assert_eq!(q_result_3.len(), 2);
+
+        assert!(query("X.in.nowhere", &w)?.is_empty());
+
+        // Nothing positive to match, so no node either
+        let q_result_4 = query("not x.y", &w)?;
+        assert_eq!(q_result_4.len(), 1);
+        assert_eq!(q_result_4[0].nid(), None);
+
+        // Numeric values order numerically
+        let mut w2 = RellTree::new();
+        for s in &["a.hp!10", "b.hp!(9.5)", "c.hp!-3", "d.hp!100"]
+        {
+            w2.add_statement(s)?;
+        }
+        let mut by_hp = query("X.hp!H", &w2)?;
+        by_hp.sort_by(|a, b| a.get("H").partial_cmp(&b.get("H")).unwrap());
+        let order: Vec<String> = by_hp.iter().map(|m| m.get("X").unwrap().to_string()).collect();
+        assert_eq!(order, vec!["c", "b", "a", "d"]);
+        assert_eq!(query("X.hp!H, not H < 10", &w2)?.len(), 2);
+        assert!(query("X.in,Y..Z", &w).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregates() -> Result<()>
+    {
+        let mut w = RellTree::new();
+        for s in &["man.carries.cabbage", "man.carries.goat", "man.carries.rock",
+                   "cabbage.weight!2", "goat.weight!(30.5)", "rock.weight!2",
+                   "goat.in!boat", "man.in!boat", "wolf.in!left"]
+        {
+            w.add_statement(s)?;
+        }
+
+        assert_eq!(aggregate("count(X : X.in!boat)", &w)?, Some(RellSymValue::Integer(2)));
+        assert_eq!(aggregate("count(X : X.in!river)", &w)?, Some(RellSymValue::Integer(0)));
+        assert_eq!(aggregate("sum(W : man.carries.X, X.weight!W)", &w)?, Some(RellSymValue::Float(34.5)));
+        assert_eq!(aggregate("min(W : man.carries.X, X.weight!W)", &w)?, Some(RellSymValue::Integer(2)));
+        assert_eq!(aggregate("max(W : man.carries.X, X.weight!W)", &w)?, Some(RellSymValue::Float(30.5)));
+        assert_eq!(aggregate("max(W : X.height!W)", &w)?, None);
+        assert_eq!(aggregate("distinct(W : man.carries.X, X.weight!W)", &w)?, Some(RellSymValue::Integer(2)));
+        assert_eq!(aggregate("min(X : X.in!Y)", &w)?, Some(RellSymValue::Literal("goat".to_string())));
+
+        let distinct = Aggregate::parse("distinct(W : man.carries.X, X.weight!W)").unwrap();
+        assert_eq!(distinct.values(&w)?.len(), 2);
+        assert_eq!(distinct.to_string(), "distinct(W : man.carries.X, X.weight!W)");
+
+        assert!(aggregate("sum(X : X.in!boat)", &w).is_err(), "Can't add literals");
+        assert!(aggregate("count(Z : X.in!boat)", &w).is_err(), "Z is not in the query");
+        assert!(aggregate("average(X : X.in!boat)", &w).is_err());
+
+        // Inside queries
+        assert_eq!(query("X.in!boat, count(Y : Y.in!boat) > 1", &w)?.len(), 2);
+        assert!(query("X.in!boat, distinct(Y : Y.in!boat, Y.carries.Z) > 1", &w)?.is_empty());
 
         Ok(())
     }
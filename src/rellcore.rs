@@ -1,27 +1,90 @@
 use std::collections::BTreeMap;
-use std::hash::Hash;
 
 // CORE
 pub type NID = usize; // NODE ID   (Monotonically increased from 1)
-pub type SID = u64;   // SYMBOL ID (Hashed from value)
+pub type SID = u64;   // SYMBOL ID (Stable hash of the value, see SymbolsTable)
 
 pub trait SIDGenerator
 {
     fn get_sid<S>(&self, sym:S) -> SID
-        where S: AsRef<str>, S: Hash;
+        where S: AsRef<str>;
+
+    fn get_sid_no_binding<S>(&self, sym:S) -> SID
+        where S: AsRef<str>;
 }
 
-// TODO: Composable errors
 pub mod errors
 {
+    use std::sync::Arc;
+    use super::{NID, SID};
+
     pub type Result<T> = std::result::Result<T, Error>;
+
+    // [start, end) into a statement, both as byte offsets (for slicing) and
+    // as char offsets (for pointing at it on screen)
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    pub struct Span
+    {
+        pub start: usize,
+        pub end: usize,
+        pub char_start: usize,
+        pub char_end: usize,
+    }
+    impl Span
+    {
+        // Takes byte offsets
+        pub fn new(statement: &str, start: usize, end: usize) -> Self
+        {
+            let char_offset = |byte_i: usize| statement.char_indices().take_while(|(i, _)| *i < byte_i).count();
+            Self { start, end, char_start: char_offset(start), char_end: char_offset(end) }
+        }
+    }
+
     #[derive(Debug, Clone)]
     pub enum Error
     {
-        InvalidChar(char, usize),
-        CustomError(String)
+        // Parsing
+        InvalidChar { ch: char, span: Span, statement: String },
+        UnterminatedLiteral { span: Span, statement: String },
+        UnexpectedToken { token: String, span: Span, statement: String },
+        NumericParse { source: std::num::ParseFloatError, span: Span, statement: String },
+        NumericOverflow { span: Span, statement: String },
+
+        // Tree
+        InvalidEdgeUpgrade { from: String, to: String },
+        IncompatibleEdge { path: String, statement: String, source: Box<Error> },
+        UnknownNode(NID),
+        RootRemoval,
+        NoTransaction,
+
+        // Symbols
+        SymbolCollision { sid: SID, existing: String, new: String },
+
+        // Rules & Runtime
+        MalformedRule { rule: String, reason: String },
+        Arithmetic { expression: String, reason: String },
+        DuplicateRule(String),
+        DuplicateFunction(String),
+        UnknownFunction(String),
+        UpdateLimit(usize),
+        AtLine { line: usize, source: Box<Error> },
+        Io(Arc<std::io::Error>),
+    }
+
+    impl std::error::Error for Error
+    {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)>
+        {
+            match self
+            {
+                Error::NumericParse { source, .. }     => Some(source),
+                Error::IncompatibleEdge { source, .. } => Some(source.as_ref()),
+                Error::AtLine { source, .. }           => Some(source.as_ref()),
+                Error::Io(source)                      => Some(source.as_ref()),
+                _ => None
+            }
+        }
     }
-    impl std::error::Error for Error {}
 
     impl std::fmt::Display for Error
     {
@@ -29,15 +92,59 @@ pub mod errors
         {
             match self
             {
-                Error::CustomError(m) => formatter.write_str(m),
-                Error::InvalidChar(ch, pos)  => formatter.write_fmt(format_args!("Invalid Char {} at {}", ch, pos))
+                Error::InvalidChar { ch, span, statement } =>
+                    write!(formatter, "Invalid char '{}' at {} in `{}`", ch, span.char_start, statement),
+                Error::UnterminatedLiteral { span, statement } =>
+                    write!(formatter, "Unterminated quoted literal at {} in `{}`", span.char_start, statement),
+                Error::UnexpectedToken { token, span, statement } =>
+                    write!(formatter, "Unexpected token {} at {}..{} in `{}`", token, span.char_start, span.char_end, statement),
+                Error::NumericParse { source, span, statement } =>
+                    write!(formatter, "Invalid number `{}` ({}) in `{}`", &statement[span.start..span.end], source, statement),
+                Error::NumericOverflow { span, statement } =>
+                    write!(formatter, "Number `{}` is out of range in `{}`", &statement[span.start..span.end], statement),
+                Error::InvalidEdgeUpgrade { from, to } =>
+                    write!(formatter, "Can't upgrade edge '{}' to '{}'", from, to),
+                Error::IncompatibleEdge { path, statement, .. } =>
+                    write!(formatter, "Incompatible edge at `{}` while adding `{}`", path, statement),
+                Error::UnknownNode(nid) =>
+                    write!(formatter, "Node {} does not exist in the tree", nid),
+                Error::RootRemoval =>
+                    write!(formatter, "ROOT cannot be removed from the tree"),
+                Error::NoTransaction =>
+                    write!(formatter, "No transaction in progress"),
+                Error::SymbolCollision { sid, existing, new } =>
+                    write!(formatter, "Symbol {} collides with {} on SID {:#x}", new, existing, sid),
+                Error::MalformedRule { rule, reason } =>
+                    write!(formatter, "Malformed rule `{}`: {}", rule, reason),
+                Error::Arithmetic { expression, reason } =>
+                    write!(formatter, "Can't evaluate `{}`: {}", expression, reason),
+                Error::DuplicateRule(name) =>
+                    write!(formatter, "Rule {} already exists", name),
+                Error::DuplicateFunction(name) =>
+                    write!(formatter, "Function {} already exists", name),
+                Error::UnknownFunction(name) =>
+                    write!(formatter, "Function {} does not exist", name),
+                Error::UpdateLimit(steps) =>
+                    write!(formatter, "Rules still change the tree after {} steps, computed posteriors need a prior that stops them", steps),
+                Error::AtLine { line, source } =>
+                    write!(formatter, "Line {}: {}", line, source),
+                Error::Io(e) =>
+                    write!(formatter, "IO error: {}", e),
             }
         }
     }
+
+    impl From<std::io::Error> for Error
+    {
+        fn from(e: std::io::Error) -> Self
+        {
+            Error::Io(Arc::new(e))
+        }
+    }
 }
 use errors::{Result, Error};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct RellN
 {
     pub edge: RellE,
@@ -48,9 +155,14 @@ impl RellN
 {
     pub const NID_INVALID: NID = 0;
 
-    pub fn insert(&mut self, sid: &SID, nid: &NID)
+    pub fn insert(&mut self, sid: &SID, nid: &NID) -> Option<NID>
     {
-        self.edge.insert(sid, nid);
+        self.edge.insert(sid, nid)
+    }
+
+    pub fn remove(&mut self, sid: &SID) -> Option<NID>
+    {
+        self.edge.remove(sid)
     }
 
     pub fn upgrade(&mut self, to_edge: &RellE) -> Result<()>
@@ -62,7 +174,7 @@ impl RellN
                 self.edge = other.clone();
                 Ok(())
             },
-            (_, _) => Err(Error::CustomError(format!("CANT UPGRADE {:?} TO {:?}", self.edge, to_edge)))
+            (_, _) => Err(Error::InvalidEdgeUpgrade { from: self.edge.to_string(), to: to_edge.to_string() })
         }
     }
 }
@@ -76,13 +188,49 @@ pub enum RellE
 }
 impl RellE
 {
-    pub fn insert(&mut self, sidref: &SID, nidref: &NID)
+    // Returns the NID of the child an Exclusive edge used to point to, if it got replaced
+    pub fn insert(&mut self, sidref: &SID, nidref: &NID) -> Option<NID>
     {
         match self
         {
             Self::Empty => panic!("Inserting in Empty Edge suggest an issue with upstream Edge upgrading"),
-            Self::NonExclusive(edge_map) => { edge_map.insert(*sidref, *nidref); }
-            Self::Exclusive(sid, nid) => { *sid = *sidref; *nid = *nidref; }
+            Self::NonExclusive(edge_map) => { edge_map.insert(*sidref, *nidref); None }
+            Self::Exclusive(sid, nid) => {
+                let replaced = *nid;
+                *sid = *sidref;
+                *nid = *nidref;
+
+                if replaced == RellN::NID_INVALID || replaced == *nidref { None }
+                else { Some(replaced) }
+            }
+        }
+    }
+
+    pub fn remove(&mut self, sidref: &SID) -> Option<NID>
+    {
+        match self
+        {
+            Self::Empty => None,
+            Self::NonExclusive(edge_map) => { edge_map.remove(sidref) },
+            Self::Exclusive(sid, nid) => {
+                if *sid == *sidref
+                {
+                    let nid = *nid;
+                    *self = Self::Empty;
+                    Some(nid)
+                }
+                else { None }
+            }
+        }
+    }
+
+    pub fn children(&self) -> Vec<NID>
+    {
+        match self
+        {
+            Self::Empty => vec![],
+            Self::NonExclusive(edge_map) => { edge_map.values().cloned().collect() },
+            Self::Exclusive(_, nid) => { vec![*nid] }
         }
     }
 
@@ -93,7 +241,7 @@ impl RellE
             Self::Empty => None,
             Self::NonExclusive(edge_map) => { edge_map.get(sidref) },
             Self::Exclusive(sid, nid) => {
-                if *sid == *sidref { Some(&nid) }
+                if *sid == *sidref { Some(nid) }
                 else { None }
             }
         }
@@ -126,15 +274,70 @@ impl std::fmt::Display for RellE
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 pub enum RellSymValue
 {
-    Numeric(f32),
+    Integer(i64),
+    Float(f64), // Never integral when it fits in an Integer, see from_f64
     Literal(String),
     Identifier(String)
 }
+impl RellSymValue
+{
+    // Canonical numeric value, so 50.0 and 50 end up being the same symbol
+    pub fn from_f64(n: f64) -> Self
+    {
+        if n.fract() == 0.0 && n >= i64::MIN as f64 && n < i64::MAX as f64
+        {
+            RellSymValue::Integer(n as i64)
+        }
+        else
+        {
+            RellSymValue::Float(n)
+        }
+    }
 
-#[derive(Debug, PartialEq, Clone)]
+    pub fn as_f64(&self) -> Option<f64>
+    {
+        match self
+        {
+            RellSymValue::Integer(i) => Some(*i as f64),
+            RellSymValue::Float(n)   => Some(*n),
+            _ => None
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool
+    {
+        self.as_f64().is_some()
+    }
+}
+
+impl PartialEq for RellSymValue
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.partial_cmp(other) == Some(std::cmp::Ordering::Equal)
+    }
+}
+
+// Numbers order numerically (Integer and Float mixed), literals and identifiers
+// lexicographically against their own kind, anything else is incomparable
+impl PartialOrd for RellSymValue
+{
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering>
+    {
+        match (self, other)
+        {
+            (RellSymValue::Integer(a), RellSymValue::Integer(b))       => Some(a.cmp(b)),
+            (RellSymValue::Literal(a), RellSymValue::Literal(b))       => Some(a.cmp(b)),
+            (RellSymValue::Identifier(a), RellSymValue::Identifier(b)) => Some(a.cmp(b)),
+            (a, b) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, PartialOrd, Clone)]
 pub struct RellSym
 {
     val: RellSymValue,
@@ -150,21 +353,41 @@ impl RellSym
     {
         match &self.get_val()
         {
-            RellSymValue::Numeric(n) =>
+            RellSymValue::Integer(i) =>
+            {
+                i.to_string()
+            },
+            RellSymValue::Float(n) =>
             {
-                n.to_string()
+                format!("{}{}{}", crate::expression::Expression::OPEN, n, crate::expression::Expression::CLOSE)
             },
-            RellSymValue::Literal(s) | RellSymValue::Identifier(s) =>
+            RellSymValue::Literal(s) =>
+            {
+                crate::parser::RellParser::quote_if_needed(s)
+            },
+            RellSymValue::Identifier(s) =>
             {
                 s.to_string()
             }
         }
     }
 
+    // Text the SID of this symbol is hashed from. Literals keep the quotes they need, so
+    // "50" and "X" can't share a SID with the number 50 or the variable X
+    pub fn sid_key(&self) -> String
+    {
+        self.get_display()
+    }
+
     pub fn get_val(&self) -> &RellSymValue
     {
         &self.val
     }
+
+    pub fn as_f64(&self) -> Option<f64>
+    {
+        self.val.as_f64()
+    }
 }
 
 impl std::fmt::Display for RellSym
@@ -1,10 +1,18 @@
 use std::collections::BTreeMap;
 use std::collections::btree_map::Iter;
 
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
-
 use crate::rellcore::*;
+use crate::rellcore::errors::*;
+
+// FNV-1a (64 bits), unlike std's DefaultHasher its output is fixed, so SIDs stay valid
+// across Rust releases, processes and serialized trees
+const FNV_OFFSET_BASIS: SID = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: SID = 0x0000_0100_0000_01b3;
+
+fn stable_hash(sym: &str) -> SID
+{
+    sym.bytes().fold(FNV_OFFSET_BASIS, |hash, byte| (hash ^ SID::from(byte)).wrapping_mul(FNV_PRIME))
+}
 
 #[derive(Debug, Default, PartialEq)]
 pub struct SymbolsTable
@@ -33,23 +41,24 @@ impl SymbolsTable
         self.get_sym(sid).unwrap().get_val()
     }
 
-    pub fn get_sid_no_binding<S>(&self, sym: S) -> SID 
-        where S: AsRef<str>, S: Hash
+    pub fn insert(&mut self, key: SID, value: RellSym) -> Result<Option<RellSym>>
     {
-        let mut hasher = DefaultHasher::new();
-        sym.hash(&mut hasher);
-        let v = hasher.finish();
-        v
-    }
+        // Two different symbols hashing to the same SID would silently alias
+        // each other in the tree, refuse them instead
+        if let Some(existing) = self.symbols.get(&key)
+        {
+            if existing.sid_key() != value.sid_key()
+            {
+                return Err(Error::SymbolCollision { sid: key, existing: existing.to_string(), new: value.to_string() });
+            }
+        }
 
-    pub fn insert(&mut self, key: SID, value: RellSym) -> Option<RellSym>
-    {
         // If insert finds a value already present with that key, it returns
         // it as part of the insertion
-        self.symbols.insert(key, value)
+        Ok(self.symbols.insert(key, value))
     }
 
-    pub fn symbols_iter(&self) -> Iter<SID, RellSym>
+    pub fn symbols_iter(&self) -> Iter<'_, SID, RellSym>
     {
         self.symbols.iter()
     }
@@ -69,11 +78,9 @@ impl SymbolsTable
 impl SIDGenerator for SymbolsTable
 {
     fn get_sid<S>(&self, sym:S) -> SID
-        where S: AsRef<str>, S: Hash
+        where S: AsRef<str>
     {
-        let mut hasher = DefaultHasher::new();
-        sym.hash(&mut hasher);
-        let v = hasher.finish();
+        let v = stable_hash(sym.as_ref());
 
         if let Some(other_sid) = self.bound_variables.get(&v)
         {
@@ -84,4 +91,44 @@ impl SIDGenerator for SymbolsTable
             v
         }
     }
+
+    fn get_sid_no_binding<S>(&self, sym: S) -> SID
+        where S: AsRef<str>
+    {
+        stable_hash(sym.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn stable_sids()
+    {
+        let st = SymbolsTable::new();
+        assert_eq!(st.get_sid(""), FNV_OFFSET_BASIS);
+        assert_eq!(st.get_sid("ROOT"), 0xe019_3e2c_8cae_2385);
+        assert_eq!(st.get_sid("goat"), 0x9cf9_ea72_0eaf_0f1e);
+        assert_eq!(st.get_sid("goat"), st.get_sid_no_binding(String::from("goat")));
+        assert_ne!(st.get_sid("goat"), st.get_sid("Goat"));
+    }
+
+    #[test]
+    fn collisions() -> Result<()>
+    {
+        let mut st = SymbolsTable::new();
+        let sid = st.get_sid("goat");
+        let goat = RellSym::new(RellSymValue::Literal("goat".to_string()));
+
+        assert_eq!(st.insert(sid, goat.clone())?, None);
+        assert_eq!(st.insert(sid, goat.clone())?, Some(goat.clone())); // Same symbol, no collision
+
+        let err = st.insert(sid, RellSym::new(RellSymValue::Literal("cabbage".to_string())));
+        assert!(matches!(err, Err(Error::SymbolCollision { sid: s, .. }) if s == sid), "Result is: {:?}", err);
+        assert_eq!(st.get_sym(&sid), Some(&goat), "Collision should not overwrite the existing symbol");
+
+        Ok(())
+    }
 }
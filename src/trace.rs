@@ -0,0 +1,88 @@
+use std::cell::RefCell;
+
+use crate::rellcore::*;
+use crate::parser::*;
+
+// Structured diagnostics. Every event is logged at TRACE level and, while recording is
+// enabled on the current thread, also kept around so it can be inspected afterwards
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceEvent
+{
+    Tokenized { statement: String, tokens: Vec<ParseToken> },
+    Parsed { statement: String, symbols: Vec<RellSym> },
+    Bound { statement: String, matches: Vec<String> },
+    FunctionCalled { call: String, triggered: bool },
+}
+
+thread_local!
+{
+    static RECORDER: RefCell<Option<Vec<TraceEvent>>> = const { RefCell::new(None) };
+}
+
+pub fn start_recording()
+{
+    RECORDER.with(|r| *r.borrow_mut() = Some(vec![]));
+}
+
+// Stops recording and returns everything recorded since start_recording
+pub fn stop_recording() -> Vec<TraceEvent>
+{
+    RECORDER.with(|r| r.borrow_mut().take()).unwrap_or_default()
+}
+
+pub fn is_recording() -> bool
+{
+    RECORDER.with(|r| r.borrow().is_some())
+}
+
+// Events are only built when someone is going to look at them, this is called from the
+// parser on every statement
+pub(crate) fn emit<F>(event: F)
+    where F: FnOnce() -> TraceEvent
+{
+    let recording = is_recording();
+    if !recording && !log_enabled!(log::Level::Trace)
+    {
+        return;
+    }
+
+    let event = event();
+    trace!("{:?}", event);
+    if recording
+    {
+        RECORDER.with(|r| if let Some(events) = r.borrow_mut().as_mut() { events.push(event) });
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::tree::*;
+    use crate::query::*;
+    use crate::rellcore::errors::*;
+
+    #[test]
+    fn recording() -> Result<()>
+    {
+        let mut w = RellTree::new();
+        w.add_statement("city.in.state")?;
+        assert!(!is_recording());
+        assert!(stop_recording().is_empty());
+
+        start_recording();
+        w.add_statement("goat.in!left")?;
+        query_on("X.in.state", &w);
+        let events = stop_recording();
+        assert!(!is_recording());
+
+        assert!(matches!(&events[0], TraceEvent::Tokenized { statement, tokens } if statement == "goat.in!left" && tokens.len() == 6));
+        assert!(matches!(&events[1], TraceEvent::Parsed { statement, symbols } if statement == "goat.in!left" && symbols.len() == 3));
+        assert!(events.contains(&TraceEvent::Bound { statement: "X.in.state".to_string(), matches: vec!["city.in.state".to_string()] }));
+
+        w.add_statement("goat.in!right")?;
+        assert!(stop_recording().is_empty(), "Nothing should be recorded after stopping");
+
+        Ok(())
+    }
+}
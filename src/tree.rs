@@ -1,4 +1,5 @@
 use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
 
 use crate::rellcore::*;
 use crate::rellcore::errors::*;
@@ -6,12 +7,22 @@ use crate::parser::*;
 use crate::symbols::*;
 
 // TREE
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub struct RellTree
 {
     pub symbols: SymbolsTable, //BTreeMap<SID, RellSym>, // SID -> Symbol Map
     pub nodes:   BTreeMap<NID, RellN>,  // NID -> Node Map
     pub next_id: NID,
+    journal: Vec<UndoEntry>,       // Undo log while a transaction is in progress
+    savepoints: Vec<(usize, NID)>, // Journal length and next_id at each (nested) begin
+}
+
+// Previous contents of whatever a transaction touched
+#[derive(Debug)]
+enum UndoEntry
+{
+    Node(NID, Option<RellN>), // None if the node didn't exist
+    Nodes(BTreeMap<NID, RellN>),
 }
 
 impl RellTree
@@ -20,10 +31,12 @@ impl RellTree
     pub fn new() -> Self
     {
         //
-        let mut ret = Self { symbols: SymbolsTable::new(), nodes: BTreeMap::new(), next_id: Self::NID_ROOT + 1 };
-        let sid = ret.symbols.get_sid("ROOT");
+        let mut ret = Self { symbols: SymbolsTable::new(), nodes: BTreeMap::new(), next_id: Self::NID_ROOT + 1,
+                             journal: vec![], savepoints: vec![] };
+        let root = RellSym::new(RellSymValue::Literal("ROOT".to_string()));
+        let sid = ret.symbols.get_sid(root.sid_key());
         ret.nodes.insert(Self::NID_ROOT, RellN { edge: RellE::NonExclusive(BTreeMap::new()), sym: sid, parent: RellN::NID_INVALID });
-        ret.symbols.insert(sid, RellSym::new(RellSymValue::Literal("ROOT".to_string())));
+        ret.symbols.insert(sid, root).unwrap(); // Empty table, can't collide
         ret
     }
 
@@ -43,23 +56,31 @@ impl RellTree
         let statement = statement.as_ref();
         let (mut statement_tree, syms) = RellParser::parse_simple_statement(statement, &self.symbols)?;
 
+        // Symbols go first, so a collision is reported before the tree is modified
+        for s in syms
+        {
+            self.add_symbol_instance(s)?;
+        }
+
         let (start_at, insert_nid) = {
 
             let mut insert_nid = Self::NID_ROOT;
             let mut start_at   = statement.len();
 
-            let mut r = self.get_mut_root();
-
             for (i, node) in statement_tree.iter().enumerate()
             {
-                if let Some(nid) = r.edge.get(&node.sym)
+                if let Some(nid) = self.nodes.get(&insert_nid).unwrap().edge.get(&node.sym)
                 {
                     insert_nid = *nid;
-                    r = self.nodes.get_mut(&insert_nid).unwrap();
-
-                    if r.edge.is_incompatible(&node.edge)
+                    if self.nodes.get(&insert_nid).unwrap().edge.is_incompatible(&node.edge)
                     {
-                        r.upgrade(&node.edge)?;
+                        self.journal_node(insert_nid);
+                        if let Err(e) = self.nodes.get_mut(&insert_nid).unwrap().upgrade(&node.edge)
+                        {
+                            return Err(Error::IncompatibleEdge { path: self.get_path(&insert_nid).unwrap(),
+                                                                 statement: statement.to_string(),
+                                                                 source: Box::new(e) });
+                        }
                     }
                 }
                 else
@@ -78,25 +99,30 @@ impl RellTree
 
         let new_nids:Vec<NID> = statement_tree.iter().skip(start_at).map(|_| self.get_next_nid()).collect();
 
+        self.journal_node(insert_nid);
         let mut new_r = self.nodes.get_mut(&insert_nid).unwrap();
         let mut prev_nid = insert_nid;
+        let mut orphaned = None;
         for (i, node) in statement_tree.iter_mut().skip(start_at).enumerate()
         {
             let new_nid = new_nids[i];
-            new_r.insert(&node.sym, &new_nid);
+            // Only the first insertion happens on a node already in the tree, so it's the
+            // only one that can overwrite an Exclusive edge
+            orphaned = orphaned.or(new_r.insert(&node.sym, &new_nid));
             node.parent = prev_nid;
             prev_nid = new_nid;
             new_r = node;
         }
 
-        for (i, node) in statement_tree.drain(start_at..).enumerate()
+        if let Some(orphan_nid) = orphaned
         {
-            self.nodes.insert(new_nids[i], node);
+            self.drop_subtree(&orphan_nid);
         }
 
-        for s in syms
+        for (i, node) in statement_tree.drain(start_at..).enumerate()
         {
-            self.add_symbol_instance(s);
+            self.journal_node(new_nids[i]);
+            self.nodes.insert(new_nids[i], node);
         }
 
         Ok(new_nids)
@@ -104,20 +130,28 @@ impl RellTree
 
     pub fn get_at_path<S>(&self, statement: S) -> Option<&RellN>
         where S: AsRef<str>
+    {
+        self.get_nid_at_path(statement).map(|nid| self.nodes.get(&nid).unwrap())
+    }
+
+    pub fn get_nid_at_path<S>(&self, statement: S) -> Option<NID>
+        where S: AsRef<str>
     {
         let statement = statement.as_ref();
         let parsed_query = RellParser::tokenize(statement, &self.symbols);
 
         if let Ok(query_tokens) = parsed_query
         {
+            let mut r_nid = Self::NID_ROOT;
             let mut r = self.get_root();
             for t in query_tokens
             {
                 match (t, &r.edge)
                 {
-                    (ParseToken::Symbol(sid, _, _), edge) => if let Some(nid) = edge.get(&sid)
+                    (ParseToken::Symbol(sid, _), edge) => if let Some(nid) = edge.get(&sid)
                     {
-                        r = self.nodes.get(&nid).unwrap();
+                        r_nid = *nid;
+                        r = self.nodes.get(nid).unwrap();
                     }
                     else
                     {
@@ -127,12 +161,128 @@ impl RellTree
                     (_,_) => {},
                 }
             }
-            return Some(r);
+            return Some(r_nid);
         }
         None
     }
 
-    fn add_symbol_instance(&mut self, sym: RellSym)
+    // Path from ROOT to the given node, e.g. brown.is!sad
+    pub fn get_path(&self, nid: &NID) -> Option<String>
+    {
+        let mut parts = vec![];
+        let mut cur_nid = *nid;
+        while cur_nid != Self::NID_ROOT
+        {
+            let node = self.nodes.get(&cur_nid)?;
+            parts.push(self.symbols.get_sym(&node.sym)?.to_string());
+            if node.parent != Self::NID_ROOT
+            {
+                parts.push(self.nodes.get(&node.parent)?.edge.to_string());
+            }
+            cur_nid = node.parent;
+        }
+        parts.reverse();
+        Some(parts.concat())
+    }
+
+    pub fn remove_statement<S>(&mut self, statement: S) -> Result<Vec<NID>>
+        where S: AsRef<str>
+    {
+        let statement = statement.as_ref();
+        // Surface parsing errors instead of treating them as a missing path
+        RellParser::tokenize(statement, &self.symbols)?;
+
+        match self.get_nid_at_path(statement)
+        {
+            Some(nid) => self.remove_subtree(&nid),
+            None      => Ok(vec![]), // Nothing known about that statement, nothing to remove
+        }
+    }
+
+    pub fn remove_subtree(&mut self, nid: &NID) -> Result<Vec<NID>>
+    {
+        if *nid == Self::NID_ROOT
+        {
+            return Err(Error::RootRemoval);
+        }
+
+        let (parent_nid, sym) = match self.nodes.get(nid)
+        {
+            Some(node) => (node.parent, node.sym),
+            None => return Err(Error::UnknownNode(*nid)),
+        };
+
+        // Detach from the parent, leaving it as a leaf if nothing else hangs from it.
+        // ROOT always keeps its NonExclusive edge so statements can still be added
+        self.journal_node(parent_nid);
+        let parent = self.nodes.get_mut(&parent_nid).unwrap();
+        parent.remove(&sym);
+        if parent_nid != Self::NID_ROOT && parent.edge.children().is_empty()
+        {
+            parent.edge = RellE::Empty;
+        }
+
+        Ok(self.drop_subtree(nid))
+    }
+
+    // Renumbers all reachable nodes from NID_ROOT onwards, dropping anything that can't be
+    // reached from ROOT. Returns the OLD NID -> NEW NID mapping
+    pub fn compact(&mut self) -> BTreeMap<NID, NID>
+    {
+        let mut remap = BTreeMap::new();
+        let mut order = vec![];
+        let mut to_visit = vec![Self::NID_ROOT];
+        while let Some(nid) = to_visit.pop()
+        {
+            remap.insert(nid, Self::NID_ROOT + order.len());
+            order.push(nid);
+
+            let mut children = self.nodes.get(&nid).unwrap().edge.children();
+            children.reverse(); // Keep the numbering in the same order as the edge maps
+            to_visit.extend(children);
+        }
+
+        if !self.savepoints.is_empty()
+        {
+            self.journal.push(UndoEntry::Nodes(self.nodes.clone()));
+        }
+
+        let mut nodes = BTreeMap::new();
+        for old_nid in order
+        {
+            let old_node = self.nodes.remove(&old_nid).unwrap();
+            let edge = match old_node.edge
+            {
+                RellE::Empty => RellE::Empty,
+                RellE::Exclusive(sid, nid) => RellE::Exclusive(sid, remap[&nid]),
+                RellE::NonExclusive(map) => RellE::NonExclusive(map.into_iter().map(|(sid, nid)| (sid, remap[&nid])).collect()),
+            };
+            let parent = remap.get(&old_node.parent).cloned().unwrap_or(RellN::NID_INVALID);
+            nodes.insert(remap[&old_nid], RellN { edge, sym: old_node.sym, parent });
+        }
+
+        self.nodes = nodes;
+        self.next_id = Self::NID_ROOT + self.nodes.len();
+        remap
+    }
+
+    fn drop_subtree(&mut self, nid: &NID) -> Vec<NID>
+    {
+        let mut removed = vec![];
+        let mut to_remove = vec![*nid];
+        while let Some(r_nid) = to_remove.pop()
+        {
+            self.journal_node(r_nid);
+            if let Some(r_node) = self.nodes.remove(&r_nid)
+            {
+                to_remove.extend(r_node.edge.children());
+                removed.push(r_nid);
+            }
+        }
+        removed
+    }
+
+    fn add_symbol_instance(&mut self, sym: RellSym) -> Result<()>
     {
         let sid = match &sym.get_val()
         {
@@ -140,16 +290,13 @@ impl RellTree
             {
                 self.symbols.get_sid_no_binding(ssym)
             },
-            RellSymValue::Literal(ssym) =>
-            {
-                self.symbols.get_sid(ssym)
-            },
-            RellSymValue::Numeric(num) =>
+            RellSymValue::Literal(_) | RellSymValue::Integer(_) | RellSymValue::Float(_) =>
             {
-                self.symbols.get_sid(format!("{}", num)) // TODO: This feels a bit redundant
+                self.symbols.get_sid_no_binding(sym.sid_key())
             },
         };
-        self.symbols.insert(sid, sym);
+        self.symbols.insert(sid, sym)?;
+        Ok(())
     }
 
     fn get_next_nid(&mut self) -> NID
@@ -161,6 +308,140 @@ impl RellTree
     }
 }
 
+// Serialization - One statement per leaf
+impl RellTree
+{
+    // Minimal set of statements that rebuilds this tree: the path to every leaf
+    pub fn to_statements(&self) -> Vec<String>
+    {
+        let mut statements = vec![];
+
+        let mut to_visit: Vec<(NID, String)> = self.get_root().edge.children().into_iter().rev().map(|nid| (nid, "".to_string())).collect();
+        while let Some((nid, prefix)) = to_visit.pop()
+        {
+            let node = self.nodes.get(&nid).unwrap();
+            let path = prefix + &self.symbols.get_sym(&node.sym).unwrap().to_string();
+
+            match &node.edge
+            {
+                RellE::Empty => statements.push(path),
+                edge =>
+                {
+                    let path = path + &edge.to_string();
+                    for child in edge.children().into_iter().rev()
+                    {
+                        to_visit.push((child, path.clone()));
+                    }
+                }
+            }
+        }
+
+        statements
+    }
+
+    pub fn from_statements<I, S>(statements: I) -> Result<Self>
+        where I: IntoIterator<Item = S>, S: AsRef<str>
+    {
+        let mut tree = Self::new();
+        for statement in statements
+        {
+            tree.add_statement(statement)?;
+        }
+        Ok(tree)
+    }
+
+    // Reads one statement per line, blank lines are ignored
+    pub fn load<R>(reader: R) -> Result<Self>
+        where R: Read
+    {
+        let mut tree = Self::new();
+        for line in BufReader::new(reader).lines()
+        {
+            let line = line?;
+            let statement = line.trim();
+            if !statement.is_empty()
+            {
+                tree.add_statement(statement)?;
+            }
+        }
+        Ok(tree)
+    }
+
+    pub fn save<W>(&self, mut writer: W) -> Result<()>
+        where W: Write
+    {
+        for statement in self.to_statements()
+        {
+            writeln!(writer, "{}", statement)?;
+        }
+        Ok(())
+    }
+}
+
+// Transactions - everything done to the nodes between begin and rollback is undone. Symbols
+// stay, they are keyed by value so unused ones are harmless
+impl RellTree
+{
+    // Can be nested, each begin needs its own commit or rollback
+    pub fn begin(&mut self)
+    {
+        self.savepoints.push((self.journal.len(), self.next_id));
+    }
+
+    pub fn commit(&mut self) -> Result<()>
+    {
+        self.savepoints.pop().ok_or(Error::NoTransaction)?;
+        if self.savepoints.is_empty()
+        {
+            self.journal.clear();
+        }
+        // else: the enclosing transaction may still roll these changes back
+        Ok(())
+    }
+
+    pub fn rollback(&mut self) -> Result<()>
+    {
+        let (journal_len, next_id) = self.savepoints.pop().ok_or(Error::NoTransaction)?;
+        while self.journal.len() > journal_len
+        {
+            match self.journal.pop().unwrap()
+            {
+                UndoEntry::Node(nid, Some(node)) => { self.nodes.insert(nid, node); },
+                UndoEntry::Node(nid, None)       => { self.nodes.remove(&nid); },
+                UndoEntry::Nodes(nodes)          => { self.nodes = nodes; },
+            }
+        }
+        self.next_id = next_id;
+        Ok(())
+    }
+
+    pub fn in_transaction(&self) -> bool
+    {
+        !self.savepoints.is_empty()
+    }
+
+    // Commits if f succeeds, rolls back and returns its error otherwise
+    pub fn transaction<F, T>(&mut self, f: F) -> Result<T>
+        where F: FnOnce(&mut Self) -> Result<T>
+    {
+        self.begin();
+        match f(self)
+        {
+            Ok(t)  => { self.commit()?; Ok(t) },
+            Err(e) => { self.rollback()?; Err(e) },
+        }
+    }
+
+    // Must be called before the node is modified, inserted or removed
+    fn journal_node(&mut self, nid: NID)
+    {
+        if self.in_transaction()
+        {
+            self.journal.push(UndoEntry::Node(nid, self.nodes.get(&nid).cloned()));
+        }
+    }
+}
+
 // Greatest Lower Bound - Union of Trees
 impl RellTree
 {
@@ -169,9 +450,8 @@ impl RellTree
         let mut glb = RellTree::new();
 
         let mut node_trios = vec![(RellTree::NID_ROOT, RellTree::NID_ROOT, RellTree::NID_ROOT)];
-        while !node_trios.is_empty()
+        while let Some((a_nid, b_nid, glb_nid)) = node_trios.pop()
         {
-            let (a_nid, b_nid, glb_nid) = node_trios.pop().unwrap();
             let a_node   = self.nodes.get(&a_nid).unwrap();
             let b_node   = other.nodes.get(&b_nid).unwrap();
 
@@ -245,13 +525,13 @@ impl RellTree
                     // if an X and a NX edges are found, they're considered compatible IFF they go
                     // to the same symbol AND the NX edge goes to no other symbols
                     //
-                    if !nex_map.contains_key(&x_sid) || nex_map.len() != 1
+                    if !nex_map.contains_key(x_sid) || nex_map.len() != 1
                     {
                         return None;
                     }
 
                     let new_nid = glb.insert_into(&glb_nid, RellN { edge: RellE::Empty, sym: *x_sid, parent: glb_nid }, true).unwrap();
-                    node_trios.push((*x_nid, *nex_map.get(&x_sid).unwrap(), new_nid));
+                    node_trios.push((*x_nid, *nex_map.get(x_sid).unwrap(), new_nid));
                 }
             }
         }
@@ -262,13 +542,85 @@ impl RellTree
             //TODO: This just stomps all values, Ref counting?
             for (sid, sym) in sym_tbl.symbols_iter()
             {
-                glb.symbols.insert(*sid, sym.clone());
+                glb.symbols.insert(*sid, sym.clone()).ok()?; // Colliding symbols can't be merged either
             }
         }
 
         Some(glb)
     }
 
+}
+
+// Least Upper Bound - Intersection of Trees
+impl RellTree
+{
+    // Only what both trees agree on, there's always one (if nothing else, ROOT)
+    pub fn least_upper_bound(&self, other: &Self) -> Self
+    {
+        let mut lub = RellTree::new();
+
+        let mut node_trios = vec![(RellTree::NID_ROOT, RellTree::NID_ROOT, RellTree::NID_ROOT)];
+        while let Some((a_nid, b_nid, lub_nid)) = node_trios.pop()
+        {
+            let a_node = self.nodes.get(&a_nid).unwrap();
+            let b_node = other.nodes.get(&b_nid).unwrap();
+
+            match (&a_node.edge, &b_node.edge)
+            {
+                (RellE::NonExclusive(a_emap), RellE::NonExclusive(b_emap)) =>
+                {
+                    // Only the symbols present in both
+                    for (&sym, a_nid) in a_emap
+                    {
+                        if let Some(b_nid) = b_emap.get(&sym)
+                        {
+                            let new_nid = lub.insert_into(&lub_nid, RellN { edge: RellE::Empty, sym, parent: lub_nid }, false).unwrap();
+                            node_trios.push((*a_nid, *b_nid, new_nid));
+                        }
+                    }
+                },
+                (RellE::Exclusive(a_sid, a_nid), RellE::Exclusive(b_sid, b_nid)) =>
+                {
+                    // Same symbol is agreed upon, different ones only agree on there being something
+                    if a_sid == b_sid
+                    {
+                        let new_nid = lub.insert_into(&lub_nid, RellN { edge: RellE::Empty, sym: *a_sid, parent: lub_nid }, true).unwrap();
+                        node_trios.push((*a_nid, *b_nid, new_nid));
+                    }
+                },
+                (RellE::Exclusive(x_sid, x_nid), RellE::NonExclusive(nex_map)) =>
+                {
+                    // A!C and B.C only agree on .C, the other symbols in B are unknown to A
+                    if let Some(nex_nid) = nex_map.get(x_sid)
+                    {
+                        let new_nid = lub.insert_into(&lub_nid, RellN { edge: RellE::Empty, sym: *x_sid, parent: lub_nid }, false).unwrap();
+                        node_trios.push((*x_nid, *nex_nid, new_nid));
+                    }
+                },
+                (RellE::NonExclusive(nex_map), RellE::Exclusive(x_sid, x_nid)) =>
+                {
+                    if let Some(nex_nid) = nex_map.get(x_sid)
+                    {
+                        let new_nid = lub.insert_into(&lub_nid, RellN { edge: RellE::Empty, sym: *x_sid, parent: lub_nid }, false).unwrap();
+                        node_trios.push((*nex_nid, *x_nid, new_nid));
+                    }
+                },
+                (RellE::Empty, _) | (_, RellE::Empty) =>
+                {
+                    // Leaf on either side, nothing else in common
+                },
+            }
+        }
+
+        // Every node in the LUB is in both trees, same SIDs and symbols
+        for (sid, sym) in self.symbols.symbols_iter()
+        {
+            lub.symbols.insert(*sid, sym.clone()).unwrap(); // Fresh table with the same contents, can't collide
+        }
+
+        lub
+    }
+
     //
     //
     //
@@ -279,6 +631,7 @@ impl RellTree
         // wastes memory
         let new_nid = self.get_next_nid();
         let sid = new_node.sym;
+        self.journal_node(*into_n);
         let insert_node = self.nodes.get_mut(into_n).unwrap();
 
         if exclusive
@@ -296,11 +649,12 @@ impl RellTree
                 //If the insertion is non-exclusive, and the node is non-exclusive,
                 //insert(..) below should take care of it, but if its an exclusive
                 //edge... not sure how we should handle this one.
-                return Err(Error::CustomError("Trying to insert non-exclusively into an exclusive node".to_string()));
+                return Err(Error::InvalidEdgeUpgrade { from: insert_node.edge.to_string(), to: RellE::NonExclusive(BTreeMap::new()).to_string() });
             }
 
             insert_node.insert(&sid, &new_nid);
         }
+        self.journal_node(new_nid);
         self.nodes.insert(new_nid,  new_node);
 
         Ok(new_nid)
@@ -314,9 +668,8 @@ impl RellTree
         let new_subgraph_nid = self.insert_into(into_n, new_subgraph_root, exclusive)?;
 
         let mut node_pairs = vec![(new_subgraph_nid, *from_n)];
-        while !node_pairs.is_empty()
+        while let Some((self_nid, c_nid)) = node_pairs.pop()
         {
-            let (self_nid, c_nid) = node_pairs.pop().unwrap();
 
             let c_node = other_tree.nodes.get(&c_nid).unwrap();
 
@@ -366,17 +719,17 @@ mod test
         assert_eq!(
             format!("{}", t),
                    "ROOT\n\
-                    -a\n\
-                    --b\n\
-                    ---d\n\
-                    ---c\n\
-                    --f\n\
-                    ---e\n\
                     -z\n\
                     --x\n\
                     --*p\n\
                     --q\n\
-                    ---r\n");
+                    ---r\n\
+                    -a\n\
+                    --b\n\
+                    ---c\n\
+                    ---d\n\
+                    --f\n\
+                    ---e\n");
         Ok(())
     }
 
@@ -394,9 +747,10 @@ mod test
         t2.add_statement("a.b")?;
         assert!(t < t2, "{} < {}", t, t2);
 
-        // This makes t > t2
+        // Each knows something the other doesn't
         t2.add_statement("a.c")?;
-        assert!(t > t2, "{} > {}", t, t2);
+        assert_eq!(t.partial_cmp(&t2), None, "{} incomparable to {}", t, t2);
+        assert_ne!(t, t2);
 
         // Verify adding new statements
         // wont break cmp
@@ -404,7 +758,16 @@ mod test
         assert!(t > t2, "{} > {}", t, t2);
 
         t3.add_statement("a.b.c")?;
-        assert!(t < t3, "{} < {}", t, t3);
+        assert_eq!(t.partial_cmp(&t3), Some(std::cmp::Ordering::Equal));
+        assert!(t <= t3 && t == t3);
+
+        // A!C knows C is the only one, B.C.D knows of D as well
+        let mut x = RellTree::new();
+        x.add_statement("a!c")?;
+        let mut y = RellTree::new();
+        y.add_statement("a.c")?;
+        y.add_statement("a.d")?;
+        assert_eq!(x.partial_cmp(&y), None);
 
         Ok(())
     }
@@ -512,6 +875,31 @@ mod test
 
         assert!(t.add_statement("t!2").is_err(), "Numeric incompatible insertion being ignored");
 
+        let mut hp = RellTree::new();
+        hp.add_statement("brown.hp!50")?;
+        assert!(hp.add_statement("brown.hp!(50.0)")?.is_empty(), "50.0 should be the same fact as 50");
+        hp.add_statement("brown.temp!(-2.5)")?;
+        hp.add_statement("brown.debt!-5")?;
+        let statements = hp.to_statements();
+        for s in &["brown.hp!50", "brown.temp!(-2.5)", "brown.debt!-5"]
+        {
+            assert!(statements.contains(&s.to_string()), "{} missing from {:?}", s, statements);
+        }
+        let mut round_trip = RellTree::from_statements(hp.to_statements())?;
+        assert!(round_trip.add_statement("brown.temp!(-2.5)")?.is_empty());
+        assert!(round_trip.get_at_path("brown.debt!-5").is_some());
+
+        // 3 -> 5 and 3.5 are different facts, and stay different once saved
+        let mut grid = RellTree::new();
+        grid.add_statement("grid.3.5")?;
+        grid.add_statement("grid.(3.5)")?;
+        let mut statements = grid.to_statements();
+        statements.sort();
+        assert_eq!(statements, vec!["grid.(3.5)", "grid.3.5"]);
+        let round_trip = RellTree::from_statements(grid.to_statements())?;
+        assert_eq!(round_trip.to_statements(), grid.to_statements());
+        assert_eq!(round_trip.nodes.len(), grid.nodes.len());
+
         t.add_statement("t.a")?;
         t.add_statement("t.b")?;
 
@@ -525,12 +913,12 @@ mod test
         assert_eq!(format!("{}", glb),
                            "ROOT\n\
                             -t\n\
-                            --b\n\
-                            --15\n\
                             --k\n\
                             ---d\n\
+                            --b\n\
+                            --c\n\
                             --a\n\
-                            --c\n");
+                            --15\n");
         Ok(())
     }
 
@@ -586,6 +974,166 @@ mod test
         Ok(())
     }
 
+    #[test]
+    fn test_remove() -> Result<()>
+    {
+        let mut w = RellTree::new();
+        w.add_statement("goat.in!left")?;
+        w.add_statement("brown.knows.stuff")?;
+        w.add_statement("brown.knows.me")?;
+        let knows_nid = w.get_nid_at_path("brown.knows").unwrap();
+        let me_nid    = w.get_nid_at_path("brown.knows.me").unwrap();
+
+        assert_eq!(w.remove_statement("goat.in.left")?.len(), 1);
+        assert!(w.get_at_path("goat.in").is_some());
+        assert!(w.get_at_path("goat.in.left").is_none());
+        assert_eq!(w.get_at_path("goat.in").unwrap().edge, RellE::Empty, "Exclusive parent should be reset");
+
+        // Parent is a leaf again, so it can take any kind of edge
+        w.add_statement("goat.in.boat")?;
+        assert!(w.get_at_path("goat.in.boat").is_some());
+
+        let removed = w.remove_statement("brown.knows")?;
+        assert_eq!(removed.len(), 3);
+        assert_eq!(removed[0], knows_nid);
+        assert!(removed.contains(&me_nid));
+        assert!(!w.nodes.contains_key(&me_nid), "Descendants should be dropped from the tree");
+        assert!(w.get_at_path("brown").is_some());
+        assert!(w.get_at_path("brown.knows").is_none());
+
+        assert_eq!(w.remove_statement("brown.knows.stuff")?, vec![]); // Nothing to remove
+        assert!(matches!(w.remove_subtree(&RellTree::NID_ROOT), Err(Error::RootRemoval)));
+        assert!(matches!(w.remove_subtree(&me_nid), Err(Error::UnknownNode(nid)) if nid == me_nid));
+
+        w.remove_statement("brown")?;
+        w.remove_statement("goat")?;
+        assert_eq!(w.nodes.len(), 1);
+        assert_eq!(format!("{}", w), "ROOT\n");
+
+        w.add_statement("brown.is!happy")?;
+        assert!(w.get_at_path("brown.is!happy").is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_exclusive_overwrite_reclaims() -> Result<()>
+    {
+        let mut w = RellTree::new();
+        w.add_statement("goat.in!left.bank.north")?;
+        let nodes_before = w.nodes.len();
+        let old_nids = w.add_statement("goat.in!right")?;
+
+        assert!(w.get_at_path("goat.in!left").is_none());
+        assert_eq!(w.nodes.len(), nodes_before - 2, "Orphaned subtree was not reclaimed");
+        assert!(w.nodes.values().all(|n| n.parent == RellN::NID_INVALID || w.nodes.contains_key(&n.parent)));
+
+        for _ in 0..10
+        {
+            w.add_statement("goat.in!left")?;
+            w.add_statement("goat.in!right")?;
+        }
+        assert_eq!(w.nodes.len(), 4);
+        assert!(w.nodes.keys().max().unwrap() > &old_nids[0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact() -> Result<()>
+    {
+        let mut w = RellTree::new();
+        w.add_statement("brown.knows.stuff")?;
+        w.add_statement("brown.is!happy")?;
+        w.add_statement("goat.in!left")?;
+        w.remove_statement("brown.knows")?;
+        w.add_statement("goat.in!right")?;
+
+        let before = format!("{}", w);
+        let happy_nid = w.get_nid_at_path("brown.is!happy").unwrap();
+
+        let remap = w.compact();
+
+        assert_eq!(format!("{}", w), before);
+        assert_eq!(w.nodes.keys().cloned().collect::<Vec<NID>>(), (RellTree::NID_ROOT..RellTree::NID_ROOT + 7).collect::<Vec<NID>>());
+        assert_eq!(w.next_id, RellTree::NID_ROOT + 7);
+        assert_eq!(w.get_nid_at_path("brown.is!happy"), Some(remap[&happy_nid]));
+        assert_eq!(w.get_at_path("brown.is").unwrap().parent, w.get_nid_at_path("brown").unwrap());
+
+        w.add_statement("brown.is!sad")?;
+        assert!(w.get_at_path("brown.is!sad").is_some());
+        assert_eq!(w.nodes.len(), 7);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bare_literals_needing_quotes() -> Result<()>
+    {
+        // Written bare, displayed quoted, and the same symbol either way
+        let mut t = RellTree::new();
+        for s in &["a b.c", "x.a\\b", "x.ab\"c"]
+        {
+            t.add_statement(s)?;
+        }
+        assert!(t.add_statement("\"a b\".c")?.is_empty());
+
+        let mut statements = t.to_statements();
+        statements.sort();
+        assert_eq!(statements, vec!["\"a b\".c", "x.\"a\\\\b\"", "x.\"ab\\\"c\""]);
+        assert!(format!("{}", t).contains("-\"a b\"\n"));
+        assert_eq!(RellTree::from_statements(&statements)?, t);
+        assert_eq!(crate::query::query("X.Y", &t)?.len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_serialization() -> Result<()>
+    {
+        let mut t = RellTree::new();
+        t.add_statement("a.b.c")?;
+        t.add_statement("a.b.d")?;
+        t.add_statement("a.f")?;
+        t.add_statement("z.x!p")?;
+        t.add_statement("z.q.15")?;
+        t.add_statement("z.q.2.5")?;
+        t.add_statement("z.r!(2.5)")?;
+        t.add_statement("brown.is!sad.today")?;
+        t.add_statement("café.in!東京")?;
+        t.add_statement("npc.name!\"Sir Robin of Camelot\"")?;
+        t.add_statement("app.version!\"3.2.1\"")?;
+
+        let statements = t.to_statements();
+        assert_eq!(statements.len(), 11, "{:?}", statements);
+        assert!(statements.contains(&"npc.name!\"Sir Robin of Camelot\"".to_string()));
+        assert!(statements.contains(&"app.version!\"3.2.1\"".to_string()));
+        assert!(statements.contains(&"café.in!東京".to_string()));
+        assert!(statements.contains(&"a.b.c".to_string()));
+        assert!(statements.contains(&"z.x!p".to_string()));
+        assert!(statements.contains(&"z.q.15".to_string()));
+        assert!(statements.contains(&"z.q.2.5".to_string()));
+        assert!(statements.contains(&"z.r!(2.5)".to_string()));
+        assert!(statements.contains(&"brown.is!sad.today".to_string()));
+
+        let t2 = RellTree::from_statements(&statements)?;
+        assert_eq!(t2.to_statements(), statements);
+        assert_eq!(format!("{}", t2), format!("{}", t));
+
+        let mut saved = vec![];
+        t.save(&mut saved)?;
+        let t3 = RellTree::load(&saved[..])?;
+        assert_eq!(t3.to_statements(), statements);
+        assert!(t3.get_at_path("z.x!p").is_some());
+        assert!(t3.get_at_path("npc.name!\"Sir Robin of Camelot\"").is_some());
+        assert!(format!("{}", t3).contains("--*\"3.2.1\"\n"));
+
+        assert!(RellTree::load(&b"a.b\n\n  a!c  \n"[..]).is_err());
+        assert_eq!(RellTree::new().to_statements(), Vec::<String>::new());
+
+        Ok(())
+    }
+
     #[test]
     fn baseline_verification() -> Result<()>
     {
@@ -606,13 +1154,140 @@ mod test
         assert!(w.get_at_path("brown!is!sad.today").is_none()); // !is can't be satisfied by .is
 
         let e = w.add_statement("brown.is.sad.today");
-        if let Err(Error::CustomError(_)) = e
-        {
-            Ok(())
-        }
-        else
+        assert!(matches!(&e, Err(Error::IncompatibleEdge { path, .. }) if path == "brown.is"), "Unexpected Result {:?}", e);
+
+        let source = std::error::Error::source(e.as_ref().unwrap_err()).unwrap().to_string();
+        assert_eq!(source, "Can't upgrade edge '!' to '.'");
+        Ok(())
+    }
+
+    #[test]
+    fn test_lub() -> Result<()>
+    {
+        let mut a = RellTree::new();
+        a.add_statement("goat.in!boat")?;
+        a.add_statement("man.in!left")?;
+        a.add_statement("man.knows.goat")?;
+        a.add_statement("man.knows.wolf")?;
+        a.add_statement("wolf.is!hungry")?;
+        a.add_statement("cabbage.is.green")?;
+
+        let mut b = RellTree::new();
+        b.add_statement("goat.in!boat.deck")?;
+        b.add_statement("man.in!right")?;
+        b.add_statement("man.knows!goat")?;
+        b.add_statement("wolf.is.hungry")?;
+        b.add_statement("wolf.is.grey")?;
+
+        let lub = a.least_upper_bound(&b);
+        let mut statements = lub.to_statements();
+        statements.sort();
+        assert_eq!(statements, vec!["goat.in!boat", "man.in", "man.knows.goat", "wolf.is.hungry"]);
+
+        // Consistent with PartialOrd, both trees know at least as much as their LUB
+        assert!(a < lub, "{}", lub);
+        assert!(b < lub, "{}", lub);
+        assert_eq!(b.least_upper_bound(&a).to_statements().len(), 4);
+
+        // Nothing in common
+        let mut c = RellTree::new();
+        c.add_statement("rock")?;
+        assert!(a.least_upper_bound(&c).to_statements().is_empty());
+
+        let lub_self = a.least_upper_bound(&a);
+        assert_eq!(lub_self, a, "A tree is its own LUB");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_eq_hash() -> Result<()>
+    {
+        use std::collections::HashSet;
+
+        // Same information, different insertion order, NIDs and symbol tables
+        let mut a = RellTree::new();
+        a.add_statement("man.in!left")?;
+        a.add_statement("goat.in!left")?;
+        a.add_statement("wolf.in!right")?;
+        a.remove_statement("wolf")?;
+
+        let mut b = RellTree::new();
+        b.add_statement("goat.in!right")?;
+        b.add_statement("goat.in!left")?;
+        b.add_statement("man.in!left")?;
+        assert_ne!(a.nodes, b.nodes);
+        assert_eq!(a, b);
+        assert_eq!(a.partial_cmp(&b), Some(std::cmp::Ordering::Equal));
+
+        let mut c = RellTree::new();
+        c.add_statement("man.in.left")?;
+        c.add_statement("goat.in!left")?;
+        assert_ne!(a, c);
+
+        let worlds: HashSet<RellTree> = vec![a, b, c, RellTree::new()].into_iter().collect();
+        assert_eq!(worlds.len(), 3);
+
+        // Symbols that display alike in a statement are still different symbols
+        for (x, y) in [("grid.3.5", "grid.(3.5)"), ("a.hp!50", "a.hp!\"50\""), ("npc.name!X", "npc.name!\"X\"")]
         {
-            Err(Error::CustomError(format!("Unexpected Result {:?}", e)))
+            let (x, y) = (RellTree::from_statements([x])?, RellTree::from_statements([y])?);
+            assert_ne!(x, y);
+            assert_eq!(x.partial_cmp(&y), None);
+            assert_eq!(vec![x, y].into_iter().collect::<HashSet<RellTree>>().len(), 2);
         }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_transactions() -> Result<()>
+    {
+        let mut w = RellTree::new();
+        w.add_statement("brown.is!happy.today")?;
+        w.add_statement("brown.knows.stuff")?;
+        let nodes_before = w.nodes.clone();
+        let next_id_before = w.next_id;
+
+        // Third statement fails, the first two are undone
+        let result = w.transaction(|t| {
+            t.add_statement("brown.is!sad")?;
+            t.add_statement("brown.likes.me")?;
+            t.add_statement("brown.is.happy")
+        });
+        assert!(matches!(result, Err(Error::IncompatibleEdge { .. })), "Unexpected Result {:?}", result);
+        assert_eq!(w.nodes, nodes_before);
+        assert_eq!(w.next_id, next_id_before);
+        assert!(!w.in_transaction());
+
+        // Removals, exclusive overwrites and compaction are undone too
+        w.begin();
+        w.remove_statement("brown.knows")?;
+        w.add_statement("brown.is!sad")?;
+        w.compact();
+        w.add_statement("me.too")?;
+        w.rollback()?;
+        assert_eq!(w.nodes, nodes_before);
+        assert!(w.get_at_path("brown.is!happy.today").is_some());
+
+        // Nested, the inner rollback only undoes its own changes
+        w.begin();
+        w.add_statement("a.b")?;
+        w.begin();
+        w.add_statement("a.c")?;
+        w.rollback()?;
+        w.begin();
+        w.add_statement("a.d")?;
+        w.commit()?;
+        assert!(w.get_at_path("a.b").is_some() && w.get_at_path("a.c").is_none() && w.get_at_path("a.d").is_some());
+        w.rollback()?; // Outer rollback undoes the committed inner transaction as well
+        assert_eq!(w.nodes, nodes_before);
+
+        assert_eq!(w.transaction(|t| t.add_statement("x.y").map(|nids| nids.len()))?, 2);
+        assert!(w.get_at_path("x.y").is_some());
+        assert!(matches!(w.commit(), Err(Error::NoTransaction)));
+        assert!(matches!(w.rollback(), Err(Error::NoTransaction)));
+
+        Ok(())
     }
 }
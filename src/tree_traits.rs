@@ -1,16 +1,15 @@
 use super::*;
 
-impl std::cmp::PartialOrd for RellTree
+impl RellTree
 {
-    /* We will say A(self) ≤ B(other), if A contains at least as much information as B
+    /* A(self) ≤ B(other) if A contains at least as much information as B
      * i.e if B is a subgraph of A which has the same root.
      */
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering>
+    fn contains_info_of(&self, other: &Self) -> bool
     {
         let mut node_pairs = vec![(RellTree::NID_ROOT, RellTree::NID_ROOT)];
-        while !node_pairs.is_empty()
+        while let Some((a_nid, b_nid)) = node_pairs.pop()
         {
-            let (a_nid, b_nid) = node_pairs.pop().unwrap();
             let a_node = self.nodes.get(&a_nid).unwrap();
             let b_node = other.nodes.get(&b_nid).unwrap();
 
@@ -20,17 +19,13 @@ impl std::cmp::PartialOrd for RellTree
                 {
                     // If both are non-exclusive, all edges in B must also exist
                     // in A
-                    for b_sid in b_emap.keys()
+                    for (b_sid, b_nid) in b_emap
                     {
-                        if !a_emap.contains_key(b_sid)
+                        match a_emap.get(b_sid)
                         {
-                            return Some(std::cmp::Ordering::Greater);
+                            Some(a_nid) => node_pairs.push((*a_nid, *b_nid)),
+                            None => return false,
                         }
-
-                        let b_nid = b_emap.get(b_sid).unwrap();
-                        let a_nid = a_emap.get(b_sid).unwrap();
-
-                        node_pairs.push((*a_nid, *b_nid));
                     }
                 },
                 (RellE::Exclusive(b_sid, b_nid), RellE::Exclusive(a_sid, a_nid)) =>
@@ -38,33 +33,101 @@ impl std::cmp::PartialOrd for RellTree
                     // If both are exclusive, they must go to the same symbol
                     if b_sid != a_sid
                     {
-                        return Some(std::cmp::Ordering::Greater);
+                        return false;
                     }
                     node_pairs.push((*a_nid, *b_nid));
                 },
                 (RellE::NonExclusive(b_emap), RellE::Exclusive(a_sid, a_nid)) =>
                 {
-                    // If A!C exists then B.C must exist
-                    if !b_emap.contains_key(a_sid)
+                    // A!C satisfies B.C, as long as B doesn't also know about B.D
+                    if b_emap.keys().any(|b_sid| b_sid != a_sid)
                     {
-                        return Some(std::cmp::Ordering::Greater);
+                        return false;
                     }
 
-                    let b_nid = b_emap.get(a_sid).unwrap();
-                    node_pairs.push((*a_nid, *b_nid));
+                    if let Some(b_nid) = b_emap.get(a_sid)
+                    {
+                        node_pairs.push((*a_nid, *b_nid));
+                    }
                 },
                 (RellE::Empty, _) =>
                 {
                     // Node is a leaf in B, Node is NOT a leaf in A - this is ok, carry on
                     continue
                 },
+                (RellE::NonExclusive(b_emap), RellE::Empty) if b_emap.is_empty() =>
+                {
+                    continue
+                },
                 (_, _) =>
                 {
-                    return Some(std::cmp::Ordering::Greater);
+                    return false;
                 }
             }
         }
-        Some(std::cmp::Ordering::Less)
+        true
+    }
+
+    // Path to every leaf as (symbol, reached through an exclusive edge), in a fixed order. Trees with
+    // the same information have the same canonical form, whatever their NIDs or symbol tables
+    fn canonical_form(&self) -> Vec<Vec<(SID, bool)>>
+    {
+        let mut paths = vec![];
+        let mut to_visit = vec![(Self::NID_ROOT, vec![])];
+        while let Some((nid, path)) = to_visit.pop()
+        {
+            let node = self.nodes.get(&nid).unwrap();
+            let exclusive = matches!(node.edge, RellE::Exclusive(_, _));
+            let children = node.edge.children();
+            if children.is_empty() && nid != Self::NID_ROOT
+            {
+                paths.push(path);
+                continue;
+            }
+
+            for child in children
+            {
+                let mut child_path = path.clone();
+                child_path.push((self.nodes.get(&child).unwrap().sym, exclusive));
+                to_visit.push((child, child_path));
+            }
+        }
+        paths.sort();
+        paths
+    }
+}
+
+impl std::cmp::PartialOrd for RellTree
+{
+    // Less means self knows more than other, None that each knows something the other doesn't
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering>
+    {
+        match (self.contains_info_of(other), other.contains_info_of(self))
+        {
+            (true, true)   => Some(std::cmp::Ordering::Equal),
+            (true, false)  => Some(std::cmp::Ordering::Less),
+            (false, true)  => Some(std::cmp::Ordering::Greater),
+            (false, false) => None,
+        }
+    }
+}
+
+// Equality is about the information in the tree, not NIDs or unused symbols
+impl std::cmp::PartialEq for RellTree
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.canonical_form() == other.canonical_form()
+    }
+}
+
+impl std::cmp::Eq for RellTree {}
+
+impl std::hash::Hash for RellTree
+{
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H)
+    {
+        self.canonical_form().hash(state);
     }
 }
 
@@ -74,9 +137,8 @@ impl std::fmt::Display for RellTree
     {
         let mut to_visit = vec![(Self::NID_ROOT, 0, false)];
 
-        while !to_visit.is_empty()
+        while let Some((nid, depth, comes_from_exclusive)) = to_visit.pop()
         {
-            let (nid, depth, comes_from_exclusive) = to_visit.pop().unwrap();
             let node = self.nodes.get(&nid).unwrap();
 
             for i in 0..depth
@@ -93,7 +155,14 @@ impl std::fmt::Display for RellTree
 
                 write!(f, "{}", tw)?;
             }
-            writeln!(f, "{}", self.symbols.get_sym(&node.sym).unwrap())?;
+            if nid == Self::NID_ROOT
+            {
+                writeln!(f, "ROOT")?; // Would be quoted otherwise
+            }
+            else
+            {
+                writeln!(f, "{}", self.symbols.get_sym(&node.sym).unwrap())?;
+            }
 
             match &node.edge
             {